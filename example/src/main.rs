@@ -4,7 +4,7 @@ use ryuji_rust::{ Renderer, FileExtension, Vars, VarValue };
 
 fn main() {
   let renderer: Renderer = Renderer::new("templates".to_string(), "components".to_string(), FileExtension::new(".html".to_string()).unwrap());
-  let mut vars: Vars = HashMap::from([
+  let vars: Vars = HashMap::from([
     ("next_post".to_string(), VarValue::HashMap(HashMap::from([
       ("title".to_string(), VarValue::String("Asdf".to_string())),
       ("slug".to_string(), VarValue::String("asdf".to_string())),
@@ -33,7 +33,7 @@ fn main() {
     ("disclaimer".to_string(), VarValue::Bool(false)),
     ("author_expected".to_string(), VarValue::Bool(true)),
   ]);
-  let rendered: String = renderer.render_template("post".to_string(), &mut vars, None).unwrap();
+  let rendered: String = renderer.render_template("post".to_string(), &vars, None).unwrap();
   println!("{}", rendered);
   //assert_eq!(rendered, "15\n<h1>title: abc</h1>\n<div>\n  <p>false</p>\n  a\n  b\n  c\n</div>\n<img/>&lt;img/&gt;");
 }
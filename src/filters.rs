@@ -0,0 +1,359 @@
+use std::cmp::Ordering;
+
+use crate::ryuji::{ Renderer, VarValue, ErrorKind };
+
+/// A filter, like `upper` in `[[ post.title|upper ]]`. Gets the value being filtered, and the args of the filter (eg, the `60` in `truncate:60`).
+pub type Filter = Box<dyn Fn(&VarValue, &[VarValue]) -> Result<VarValue, ErrorKind> + Send + Sync>;
+
+/// A function, like `route` in `[[ route("post", post.slug) ]]`. Gets the args it was called with.
+pub type Function = Box<dyn Fn(&[VarValue]) -> Result<VarValue, ErrorKind> + Send + Sync>;
+
+pub type BuiltinFilter = fn(&VarValue, &[VarValue]) -> Result<VarValue, ErrorKind>;
+
+/// Built in filters whose arg is an attribute name, which can be written without quotes (eg, `posts|map:title` is the same as `posts|map:"title"`).
+pub const ATTR_FILTERS: [&str; 2] = ["sort_by", "map"];
+
+/// Get the built in filter with the name, if there is one.
+pub fn get_builtin(name: &str) -> Option<BuiltinFilter> {
+  match name {
+    "upper" => Some(upper),
+    "lower" => Some(lower),
+    "title" => Some(title),
+    "trim" => Some(trim),
+    "truncate" => Some(truncate),
+    "replace" => Some(replace),
+    "slugify" => Some(slugify),
+    "urlencode" => Some(urlencode),
+    "striptags" => Some(striptags),
+    "wordcount" => Some(wordcount),
+    "nl2br" => Some(nl2br),
+    "escape" => Some(escape),
+    "escape_js" => Some(escape_js),
+    "length" => Some(length),
+    "join" => Some(join),
+    "first" => Some(first),
+    "last" => Some(last),
+    "reverse" => Some(reverse),
+    "sort" => Some(sort),
+    "sort_by" => Some(sort_by),
+    "slice" => Some(slice),
+    "unique" => Some(unique),
+    "map" => Some(map),
+    "default" => Some(default),
+    _ => None,
+  }
+}
+
+//error if there are not between `min` and `max` args
+fn check_args(filter_name: &str, args: &[VarValue], min: usize, max: usize) -> Result<(), ErrorKind> {
+  if args.len() < min || args.len() > max {
+    let expected: String = if min == max { min.to_string() } else { format!("{} to {}", min, max) };
+    return Err(ErrorKind::BadArgument(format!("`{}` filter takes {} args, got {}", filter_name, expected, args.len())));
+  }
+  Ok(())
+}
+
+//strings, numbers and booleans can be used as text, vectors and hashmaps cannot
+fn get_text(filter_name: &str, value: &VarValue) -> Result<String, ErrorKind> {
+  match value {
+    VarValue::String(string) => Ok(string.clone()),
+    VarValue::Vec(_) | VarValue::HashMap(_) => Err(ErrorKind::BadArgument(format!("`{}` filter cannot be used on a {}", filter_name, value.type_name()))),
+    _ => Ok(value.to_string()),
+  }
+}
+
+fn get_usize(filter_name: &str, value: &VarValue) -> Result<usize, ErrorKind> {
+  match value {
+    VarValue::U32(integer) => Ok(*integer as usize),
+    VarValue::F64(decimal) if decimal.fract() == 0.0 && *decimal >= 0.0 => Ok(*decimal as usize),
+    _ => Err(ErrorKind::BadArgument(format!("`{}` filter expected a whole number arg, got {}", filter_name, value.type_name()))),
+  }
+}
+
+fn get_vec<'a>(filter_name: &str, value: &'a VarValue) -> Result<&'a Vec<VarValue>, ErrorKind> {
+  match value {
+    VarValue::Vec(vec_value) => Ok(vec_value),
+    _ => Err(ErrorKind::BadArgument(format!("`{}` filter can only be used on a vector, not a {}", filter_name, value.type_name()))),
+  }
+}
+
+//the value of `attr` (eg, `author.name`) in a hashmap item of a vector
+fn get_attr<'a>(filter_name: &str, item: &'a VarValue, attr: &str) -> Result<&'a VarValue, ErrorKind> {
+  match item {
+    VarValue::HashMap(hashmap_value) => Renderer::get_var(attr.to_string(), hashmap_value),
+    _ => Err(ErrorKind::BadArgument(format!("`{}` filter needs the items to be hashmaps, not a {}", filter_name, item.type_name()))),
+  }
+}
+
+//sorts the values (stable, so equal values stay in the same order), erroring if any two cannot be compared
+fn sort_values<T>(filter_name: &str, items: &mut [T], get_value: impl Fn(&T) -> &VarValue) -> Result<(), ErrorKind> {
+  let mut error: Option<ErrorKind> = None;
+  items.sort_by(|a, b| {
+    let (a, b): (&VarValue, &VarValue) = (get_value(a), get_value(b));
+    a.compare(b).unwrap_or_else(|| {
+      error.get_or_insert(ErrorKind::BadArgument(format!("`{}` filter cannot compare {} and {}", filter_name, a.type_name(), b.type_name())));
+      Ordering::Equal
+    })
+  });
+  match error {
+    Some(error) => Err(error),
+    None => Ok(()),
+  }
+}
+
+fn upper(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("upper", args, 0, 0)?;
+  Ok(VarValue::String(get_text("upper", value)?.to_uppercase()))
+}
+
+fn lower(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("lower", args, 0, 0)?;
+  Ok(VarValue::String(get_text("lower", value)?.to_lowercase()))
+}
+
+//capitalize the first letter of each word, and lowercase the rest
+fn title(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("title", args, 0, 0)?;
+  let mut titled: String = String::new();
+  let mut word_start: bool = true;
+  for current_char in get_text("title", value)?.chars() {
+    if word_start {
+      titled.extend(current_char.to_uppercase());
+    } else {
+      titled.extend(current_char.to_lowercase());
+    }
+    word_start = current_char.is_whitespace() || current_char == '-';
+  }
+  Ok(VarValue::String(titled))
+}
+
+fn trim(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("trim", args, 0, 0)?;
+  Ok(VarValue::String(get_text("trim", value)?.trim().to_string()))
+}
+
+//`truncate:<max chars>` or `truncate:<max chars>:<end>`, the end (default "...") is added if anything was cut off.
+//the end does not count towards the max chars, so `"abc"|truncate:0` is "..."
+fn truncate(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("truncate", args, 1, 2)?;
+  let text: String = get_text("truncate", value)?;
+  let max_chars: usize = get_usize("truncate", &args[0])?;
+  if text.chars().count() <= max_chars {
+    return Ok(VarValue::String(text));
+  }
+  let end: String = match args.get(1) {
+    Some(end) => get_text("truncate", end)?,
+    None => "...".to_string(),
+  };
+  Ok(VarValue::String(text.chars().take(max_chars).collect::<String>()+&end))
+}
+
+//`replace:<from>:<to>`, replaces all of them
+fn replace(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("replace", args, 2, 2)?;
+  let from: String = get_text("replace", &args[0])?;
+  if from.is_empty() {
+    return Err(ErrorKind::BadArgument("`replace` filter cannot replace an empty string".to_string()));
+  }
+  Ok(VarValue::String(get_text("replace", value)?.replace(&from, &get_text("replace", &args[1])?)))
+}
+
+//eg "Hello, World!" becomes "hello-world". letters and numbers are kept even if they are not ascii ("Ça va" becomes "ça-va"), so use `urlencode` after if it has to be ascii
+fn slugify(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("slugify", args, 0, 0)?;
+  let mut slug: String = String::new();
+  for current_char in get_text("slugify", value)?.chars() {
+    if current_char.is_alphanumeric() {
+      slug.extend(current_char.to_lowercase());
+    } else if !slug.is_empty() && !slug.ends_with('-') {
+      //anything else becomes a single dash
+      slug.push('-');
+    }
+  }
+  Ok(VarValue::String(slug.trim_end_matches('-').to_string()))
+}
+
+//percent encode everything but letters, numbers and `-_.~`, so it can be used in a url query or path
+fn urlencode(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("urlencode", args, 0, 0)?;
+  let mut encoded: String = String::new();
+  for byte in get_text("urlencode", value)?.bytes() {
+    if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+      encoded.push(byte as char);
+    } else {
+      encoded += &format!("%{:02X}", byte);
+    }
+  }
+  Ok(VarValue::String(encoded))
+}
+
+//removes anything that looks like a html tag
+fn striptags(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("striptags", args, 0, 0)?;
+  let mut stripped: String = String::new();
+  let mut in_tag: bool = false;
+  for current_char in get_text("striptags", value)?.chars() {
+    if current_char == '<' {
+      in_tag = true;
+    } else if current_char == '>' && in_tag {
+      in_tag = false;
+    } else if !in_tag {
+      stripped.push(current_char);
+    }
+  }
+  Ok(VarValue::String(stripped))
+}
+
+fn wordcount(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("wordcount", args, 0, 0)?;
+  Ok(VarValue::U32(get_text("wordcount", value)?.split_whitespace().count() as u32))
+}
+
+//escapes the text, then adds a `<br>` before every newline. use with `html:` (eg, `[[ html:bio|nl2br ]]`), or the `<br>`s get sanitized
+fn nl2br(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("nl2br", args, 0, 0)?;
+  Ok(VarValue::String(escape_html(&get_text("nl2br", value)?).replace("\r\n", "\n").replace('\n', "<br>\n")))
+}
+
+fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+//unlike `Renderer::sanitize`, also escapes `&` and quotes, so it is safe inside of attributes (even with `html:`)
+fn escape(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("escape", args, 0, 0)?;
+  Ok(VarValue::String(escape_html(&get_text("escape", value)?)))
+}
+
+//for putting text inside of a javascript string, in either quotes. `<` and `>` are escaped too, so `</script>` cannot end the script
+fn escape_js(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("escape_js", args, 0, 0)?;
+  let mut escaped: String = String::new();
+  for current_char in get_text("escape_js", value)?.chars() {
+    match current_char {
+      '\\' => escaped += "\\\\",
+      '"' => escaped += "\\\"",
+      '\'' => escaped += "\\'",
+      '\n' => escaped += "\\n",
+      '\r' => escaped += "\\r",
+      '\t' => escaped += "\\t",
+      '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => escaped += &format!("\\u{:04X}", current_char as u32),
+      _ => escaped.push(current_char),
+    }
+  }
+  Ok(VarValue::String(escaped))
+}
+
+//number of items in a vector or hashmap, or chars in a string
+fn length(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("length", args, 0, 0)?;
+  let length: usize = match value {
+    VarValue::Vec(vec_value) => vec_value.len(),
+    VarValue::HashMap(hashmap_value) => hashmap_value.len(),
+    VarValue::String(string) => string.chars().count(),
+    _ => return Err(ErrorKind::BadArgument(format!("`length` filter cannot be used on a {}", value.type_name()))),
+  };
+  Ok(VarValue::U32(length as u32))
+}
+
+//`join` or `join:<separator>`, the items should be strings, numbers or booleans
+fn join(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("join", args, 0, 1)?;
+  let separator: String = match args.first() {
+    Some(separator) => get_text("join", separator)?,
+    None => String::new(),
+  };
+  let items: Vec<String> = get_vec("join", value)?.iter().map(|item| get_text("join", item)).collect::<Result<_, _>>()?;
+  Ok(VarValue::String(items.join(&separator)))
+}
+
+fn first(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("first", args, 0, 0)?;
+  get_vec("first", value)?.first().cloned().ok_or(ErrorKind::BadArgument("`first` filter used on an empty vector".to_string()))
+}
+
+fn last(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("last", args, 0, 0)?;
+  get_vec("last", value)?.last().cloned().ok_or(ErrorKind::BadArgument("`last` filter used on an empty vector".to_string()))
+}
+
+//reverses a vector, or the chars of a string
+fn reverse(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("reverse", args, 0, 0)?;
+  match value {
+    VarValue::String(string) => Ok(VarValue::String(string.chars().rev().collect())),
+    _ => Ok(VarValue::Vec(get_vec("reverse", value)?.iter().rev().cloned().collect())),
+  }
+}
+
+//numbers and strings, smallest first
+fn sort(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("sort", args, 0, 0)?;
+  let mut items: Vec<VarValue> = get_vec("sort", value)?.clone();
+  sort_values("sort", &mut items, |item| item)?;
+  Ok(VarValue::Vec(items))
+}
+
+//`sort_by:<attr>`, sorts hashmaps by one of their values, eg `posts|sort_by:date`
+fn sort_by(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("sort_by", args, 1, 1)?;
+  let attr: String = get_text("sort_by", &args[0])?;
+  let items: &Vec<VarValue> = get_vec("sort_by", value)?;
+  //get the values to sort by first, so a missing one is an error
+  let mut keyed: Vec<(&VarValue, &VarValue)> = items.iter().map(|item| Ok((get_attr("sort_by", item, &attr)?, item))).collect::<Result<_, ErrorKind>>()?;
+  sort_values("sort_by", &mut keyed, |(key, _)| key)?;
+  Ok(VarValue::Vec(keyed.into_iter().map(|(_, item)| item.clone()).collect()))
+}
+
+//`slice:<start>` or `slice:<start>:<end>` (end not included), of a vector or the chars of a string. out of range is fine, eg `slice:0:10` of 3 items is the 3 items
+fn slice(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("slice", args, 1, 2)?;
+  let start: usize = get_usize("slice", &args[0])?;
+  let end: Option<usize> = args.get(1).map(|end| get_usize("slice", end)).transpose()?;
+  let bounds = |length: usize| -> (usize, usize) {
+    let end: usize = end.unwrap_or(length).min(length);
+    (start.min(end), end)
+  };
+  match value {
+    VarValue::String(string) => {
+      let chars: Vec<char> = string.chars().collect();
+      let (start, end): (usize, usize) = bounds(chars.len());
+      Ok(VarValue::String(chars[start..end].iter().collect()))
+    },
+    _ => {
+      let items: &Vec<VarValue> = get_vec("slice", value)?;
+      let (start, end): (usize, usize) = bounds(items.len());
+      Ok(VarValue::Vec(items[start..end].to_vec()))
+    },
+  }
+}
+
+//removes repeated items, keeping the first one
+fn unique(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("unique", args, 0, 0)?;
+  let mut items: Vec<VarValue> = Vec::new();
+  for item in get_vec("unique", value)? {
+    if !items.iter().any(|existing| existing.equals(item)) {
+      items.push(item.clone());
+    }
+  }
+  Ok(VarValue::Vec(items))
+}
+
+//`map:<attr>`, gets one of the values of each hashmap, eg `posts|map:title|join:", "`
+fn map(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("map", args, 1, 1)?;
+  let attr: String = get_text("map", &args[0])?;
+  let items: Vec<VarValue> = get_vec("map", value)?.iter().map(|item| get_attr("map", item, &attr).cloned()).collect::<Result<_, _>>()?;
+  Ok(VarValue::Vec(items))
+}
+
+//`default:<value>`, used instead if the value is not truthy
+fn default(value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+  check_args("default", args, 1, 1)?;
+  if value.is_truthy() {
+    Ok(value.clone())
+  } else {
+    Ok(args[0].clone())
+  }
+}
@@ -1,7 +1,10 @@
 //! Ryuji-Rust is an implementation of the Ryuji templating language in Rust.
 pub mod ryuji;
+pub mod loader;
+pub mod filters;
 
-pub use ryuji::{ Renderer, Vars, VarValue, FileExtension };
+pub use ryuji::{ Renderer, Template, Vars, VarValue, FileExtension, ErrorKind, TemplateKind, CacheMode };
+pub use loader::{ TemplateLoader, FileSystemLoader, MemoryLoader, ChainLoader };
 
 #[cfg(test)]
 mod tests {
@@ -17,6 +20,7 @@ mod tests {
   }
 
   #[test]
+  #[allow(clippy::unnecessary_to_owned)] //`&String`s, to make sure they still work now that `&str`s are taken
   fn concat_path_test() {
     assert_eq!(ryuji::Renderer::concat_path(&"abc/".to_string(), &"/tree.html".to_string()), "abc/tree.html".to_string());
     assert_eq!(ryuji::Renderer::concat_path(&"/abc/".to_string(), &"/tree.html".to_string()), "/abc/tree.html".to_string());
@@ -26,12 +30,14 @@ mod tests {
   }
 
   #[test]
+  #[allow(clippy::unnecessary_to_owned)] //`&String`s, to make sure they still work now that `&str`s are taken
   fn sanitize_test() {
     assert_eq!(ryuji::Renderer::sanitize(&"asdf".to_string()), "asdf".to_string());
     assert_eq!(ryuji::Renderer::sanitize(&"<script>a</script>".to_string()), "&lt;script&gt;a&lt;/script&gt;".to_string());
   }
 
   #[test]
+  #[allow(clippy::unnecessary_to_owned)] //`&String`s, to make sure they still work now that `&str`s are taken
   fn var_name_legality_test() {
     assert!(ryuji::Renderer::check_var_name_legality(&"asdf".to_string(), true).is_ok());
     assert!(ryuji::Renderer::check_var_name_legality(&"random/abc".to_string(), true).is_ok());
@@ -43,6 +49,7 @@ mod tests {
   }
 
   #[test]
+  #[allow(clippy::unnecessary_to_owned)] //`&String`s, to make sure they still work now that `&str`s are taken
   fn find_syntax_matches_test() {
     assert_eq!(ryuji::Renderer::find_syntax_matches(&"[[ test.e ]]\n[[]]\nyay [[ if:yay ]]".to_string()), vec![
       ryuji::SyntaxMatch {
@@ -55,12 +62,52 @@ mod tests {
       },
     ]);
     assert_eq!(ryuji::Renderer::find_syntax_matches(&"lorem\n[[ \na ]]\nhello [[ na=me ]]".to_string()), vec![]);
+    //tags with spaces, operators or strings are only syntax if they are statements or valid expressions
+    assert_eq!(ryuji::Renderer::find_syntax_matches("[[ see also ]] const m = [[ 1, 2 ]]; [[ a == 1 ]] [[ elif:a = ]]").iter().map(|syntax_match| syntax_match.index).collect::<Vec<usize>>(), vec![37, 50]);
+    //strings can have anything inside, even "]]"
+    assert_eq!(ryuji::Renderer::find_syntax_matches("[[ if:a == \"[b] ]] c;\" ]] [[ if:a == \"\nb\" ]]"), vec![
+      ryuji::SyntaxMatch {
+        index: 0,
+        content: "[[ if:a == \"[b] ]] c;\" ]]".to_string(),
+      },
+    ]);
+  }
+
+  #[test]
+  fn unicode_test() {
+    //indexes are in bytes, so they can be used to slice the template
+    assert_eq!(ryuji::Renderer::find_syntax_matches("café 🦀 [[ a ]] 日本語[[ b ]]"), vec![
+      ryuji::SyntaxMatch {
+        index: 11,
+        content: "[[ a ]]".to_string(),
+      },
+      ryuji::SyntaxMatch {
+        index: 28,
+        content: "[[ b ]]".to_string(),
+      },
+    ]);
+    assert_eq!(ryuji::Renderer::find_syntax_matches("[[ é ]]"), vec![]);
+    assert_eq!(ryuji::Renderer::find_syntax_matches("é"), vec![]);
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("name".to_string(), ryuji::VarValue::String("Zoë".to_string())),
+      ("words".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("こんにちは".to_string()),
+        ryuji::VarValue::String("😀".to_string()),
+      ])),
+      ("poem".to_string(), ryuji::VarValue::String("ü\nö".to_string())),
+    ]);
+    let rendered: String = renderer.render("<p>Grüße, [[ name ]]! 👋</p>\n[[ for:words:word ]]«[[ word ]]»[[ endfor ]]\n  ñ [[ poem ]] ✓".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered, "<p>Grüße, Zoë! 👋</p>\n«こんにちは»«😀»\n  ñ ü\n  ö ✓");
+    //columns are counted in chars
+    let error: ryuji::ErrorKind = renderer.render("ä\n日本 [[ missing ]]".to_string(), &vars, None).unwrap_err();
+    assert_eq!((error.location().unwrap().line, error.location().unwrap().column), (2, 4));
   }
 
   #[test]
   fn variable_test() {
     let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
-    let mut vars: ryuji::Vars = std::collections::HashMap::from([
+    let vars: ryuji::Vars = std::collections::HashMap::from([
       ("a".to_string(), ryuji::VarValue::U32(15)),
       ("b".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
         ("c".to_string(), ryuji::VarValue::String("abc".to_string())),
@@ -69,14 +116,14 @@ mod tests {
       ("testing_123".to_string(), ryuji::VarValue::String("a\nb\nc".to_string())),
       ("img".to_string(), ryuji::VarValue::String("<img/>".to_string())),
     ]);
-    let rendered: String = renderer.render("[[ a ]]\n<h1>title: [[ b.c ]]</h1>\n<div>\n  <p>[[ d ]]</p>\n  [[ testing_123 ]]\n</div>\n[[ html:img ]][[ img ]]".to_string(), &mut vars, None).unwrap();
+    let rendered: String = renderer.render("[[ a ]]\n<h1>title: [[ b.c ]]</h1>\n<div>\n  <p>[[ d ]]</p>\n  [[ testing_123 ]]\n</div>\n[[ html:img ]][[ img ]]".to_string(), &vars, None).unwrap();
     assert_eq!(rendered, "15\n<h1>title: abc</h1>\n<div>\n  <p>false</p>\n  a\n  b\n  c\n</div>\n<img/>&lt;img/&gt;");
   }
 
   #[test]
   fn if_test() {
     let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
-    let mut vars: ryuji::Vars = std::collections::HashMap::from([
+    let vars: ryuji::Vars = std::collections::HashMap::from([
       ("koalas_list".to_string(), ryuji::VarValue::Vec(Vec::new())),
       ("oak".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
         ("is_tree".to_string(), ryuji::VarValue::Bool(true)),
@@ -91,24 +138,120 @@ mod tests {
         ("is_not_tree".to_string(), ryuji::VarValue::Bool(true)),
       ]))),
     ]);
-    let rendered: String = renderer.render("[[ if:koalas_list ]]<p>We have a list of koalas</p>\n[[ endif ]]<p>Dave is [[ if:dave.is_not_tree ]]not a tree[[ endif ]][[ if:dave.is_tree ]]a tree[[ endif ]]</p>\n[[ if:pine.is_tree:oak.is_tree ]]<b>Oak and pine are both trees.</b>[[ endif ]]\n[[ if:dave.is_tree:!oak.is_tree ]]<i>But Dave and Oak are different. One of them is a tree, and one of them is not a tree.</i>[[ endif ]]".to_string(), &mut vars, None).unwrap();
+    let rendered: String = renderer.render("[[ if:koalas_list ]]<p>We have a list of koalas</p>\n[[ endif ]]<p>Dave is [[ if:dave.is_not_tree ]]not a tree[[ endif ]][[ if:dave.is_tree ]]a tree[[ endif ]]</p>\n[[ if:pine.is_tree:oak.is_tree ]]<b>Oak and pine are both trees.</b>[[ endif ]]\n[[ if:dave.is_tree:!oak.is_tree ]]<i>But Dave and Oak are different. One of them is a tree, and one of them is not a tree.</i>[[ endif ]]".to_string(), &vars, None).unwrap();
     assert_eq!(rendered, "<p>Dave is not a tree</p>\n<b>Oak and pine are both trees.</b>\n<i>But Dave and Oak are different. One of them is a tree, and one of them is not a tree.</i>");
   }
 
+  #[test]
+  fn comparison_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("post".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("views".to_string(), ryuji::VarValue::U32(150)),
+        ("rating".to_string(), ryuji::VarValue::F64(4.5)),
+        ("status".to_string(), ryuji::VarValue::String("draft".to_string())),
+        ("time".to_string(), ryuji::VarValue::String("12:30".to_string())),
+      ]))),
+      ("five".to_string(), ryuji::VarValue::U32(5)),
+      ("five_f64".to_string(), ryuji::VarValue::F64(5.0)),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ if:post.views > 100 ]]popular[[ else ]]unpopular[[ endif ]]").unwrap(), "popular");
+    assert_eq!(render("[[ if:post.views <= 100 ]]unpopular[[ else ]]popular[[ endif ]]").unwrap(), "popular");
+    assert_eq!(render("[[ if:100 < post.views ]]popular[[ endif ]]").unwrap(), "popular");
+    assert_eq!(render("[[ if:post.status == \"draft\" ]]draft[[ elif:post.status == 'published' ]]published[[ endif ]]").unwrap(), "draft");
+    assert_eq!(render("[[ if:post.status != \"draft\" ]]not draft[[ endif ]]").unwrap(), "");
+    //strings can have colons
+    assert_eq!(render("[[ if:post.time == \"12:30\" ]]lunch[[ endif ]]").unwrap(), "lunch");
+    //u32 and f64 are compared by value
+    assert_eq!(render("[[ if:post.rating >= 4.5 ]]a[[ endif ]][[ if:post.rating < 5 ]]b[[ endif ]][[ if:five == 5.0 ]]c[[ endif ]][[ if:five:five_f64 ]]d[[ endif ]][[ if:-1 < five ]]e[[ endif ]]").unwrap(), "abcde");
+    assert_eq!(render("[[ if:five == true ]]a[[ endif ]][[ if:\"5\" == five ]]b[[ endif ]][[ if:true ]]c[[ endif ]]").unwrap(), "c");
+    //strings are compared alphabetically
+    assert_eq!(render("[[ if:\"apple\" < \"banana\" ]]yes[[ endif ]]").unwrap(), "yes");
+    //can't order different types, and bad syntax
+    assert!(render("[[ if:post.status > 1 ]][[ endif ]]").is_err());
+    assert!(render("[[ if:post.views > ]][[ endif ]]").is_err());
+    assert!(render("[[ if:post.views > 1 2 ]][[ endif ]]").is_err());
+    assert!(render("[[ if:post.views = 1 ]][[ endif ]]").is_err());
+    assert!(render("[[ if:1 < five < 9 ]][[ endif ]]").is_err());
+  }
+
+  #[test]
+  fn boolean_logic_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("logged_in".to_string(), ryuji::VarValue::Bool(true)),
+      ("is_admin".to_string(), ryuji::VarValue::Bool(false)),
+      ("name".to_string(), ryuji::VarValue::String("dave".to_string())),
+      ("empty".to_string(), ryuji::VarValue::String("".to_string())),
+      ("views".to_string(), ryuji::VarValue::U32(150)),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ if:logged_in and is_admin ]]a[[ endif ]][[ if:logged_in or is_admin ]]b[[ endif ]][[ if:not is_admin ]]c[[ endif ]][[ if:!is_admin ]]d[[ endif ]]").unwrap(), "bcd");
+    //non-empty strings are truthy
+    assert!(ryuji::VarValue::String("a".to_string()).is_truthy());
+    assert!(!ryuji::VarValue::String("".to_string()).is_truthy());
+    assert_eq!(render("[[ if:name ]]a[[ endif ]][[ if:not empty ]]b[[ endif ]][[ if:empty or name ]]c[[ endif ]]").unwrap(), "abc");
+    //not before and before or, parentheses to group
+    assert_eq!(render("[[ if:is_admin and logged_in or views > 100 ]]a[[ endif ]][[ if:is_admin and (logged_in or views > 100) ]]b[[ endif ]]").unwrap(), "a");
+    assert_eq!(render("[[ if:not is_admin and not (views < 100 or name != \"dave\") ]]a[[ endif ]][[ if:not not logged_in ]]b[[ endif ]]").unwrap(), "ab");
+    assert_eq!(render("[[ if:is_admin ]]a[[ elif:((logged_in)) and views != 150 ]]b[[ else ]]c[[ endif ]]").unwrap(), "c");
+    //right side is not evaluated if not needed
+    assert_eq!(render("[[ if:logged_in or missing ]]a[[ endif ]][[ if:is_admin and missing ]]b[[ endif ]]").unwrap(), "a");
+    //errors should say where the problem is
+    let error: ryuji::ErrorKind = render("[[ if:(logged_in or is_admin ]][[ endif ]]").unwrap_err();
+    assert!(error.without_location().to_string().contains("Missing `)` for the `(` at char 1"));
+    let error: ryuji::ErrorKind = render("[[ if:logged_in and and is_admin ]][[ endif ]]").unwrap_err();
+    assert!(error.without_location().to_string().contains("Unexpected `and` at char 15"));
+    assert!(render("[[ if:logged_in and ]][[ endif ]]").is_err());
+    assert!(render("[[ if:logged_in) ]][[ endif ]]").is_err());
+    assert!(render("[[ if:() ]][[ endif ]]").is_err());
+    assert!(render("[[ if:not ]][[ endif ]]").is_err());
+  }
+
+  #[test]
+  fn else_elif_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("dave".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("is_tree".to_string(), ryuji::VarValue::Bool(false)),
+        ("is_not_tree".to_string(), ryuji::VarValue::Bool(true)),
+      ]))),
+      ("things".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("oak".to_string()),
+        ryuji::VarValue::String("dave".to_string()),
+        ryuji::VarValue::String("rock".to_string()),
+      ])),
+      ("oak".to_string(), ryuji::VarValue::String("oak".to_string())),
+      ("dave_name".to_string(), ryuji::VarValue::String("dave".to_string())),
+    ]);
+    let rendered: String = renderer.render("<p>Dave is [[ if:dave.is_tree ]]a tree[[ else ]]not a tree[[ endif ]]</p>".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered, "<p>Dave is not a tree</p>");
+    //elif, and nested inside a for loop, with another if inside of a branch
+    let rendered_for: String = renderer.render("[[ for:things:thing ]][[ if:thing:oak ]]tree[[ elif:thing:dave_name ]][[ if:dave.is_tree ]]tree[[ else ]]person[[ endif ]][[ elif:dave.is_tree ]]never[[ else ]]other[[ endif ]],[[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_for, "tree,person,other,");
+    //no branch passes and there is no else
+    let rendered_none: String = renderer.render("a[[ if:dave.is_tree ]]b[[ elif:oak:!oak ]]c[[ endif ]]d".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_none, "ad");
+    assert!(renderer.render("[[ if:dave.is_tree ]]a[[ else ]]b[[ elif:oak ]]c[[ endif ]]".to_string(), &vars, None).is_err());
+    assert!(renderer.render("[[ if:dave.is_tree ]]a[[ else ]]b".to_string(), &vars, None).is_err());
+    assert!(renderer.render("[[ for:things ]][[ else ]][[ endfor ]]".to_string(), &vars, None).is_err());
+  }
+
   #[test]
   fn for_loop_test() {
     //am lazy so these tests are copied from typescript ryuji's tests, more or less
     let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
 
     //empty for loop test
-    let mut vars_empty: ryuji::Vars = std::collections::HashMap::from([
+    let vars_empty: ryuji::Vars = std::collections::HashMap::from([
       ("loop_over".to_string(), ryuji::VarValue::Vec(Vec::new())),
     ]);
-    let rendered_empty: String = renderer.render("<ul>\n  [[ for:loop_over ]]a[[ endfor ]]\n</ul>\n<p>[[ for:loop_over:item ]][[ endfor ]]</p>".to_string(), &mut vars_empty, None).unwrap();
+    let rendered_empty: String = renderer.render("<ul>\n  [[ for:loop_over ]]a[[ endfor ]]\n</ul>\n<p>[[ for:loop_over:item ]][[ endfor ]]</p>".to_string(), &vars_empty, None).unwrap();
     assert_eq!(rendered_empty, "<ul>\n  \n</ul>\n<p></p>");
 
     //for loop with more vars and if statement test
-    let mut vars_max: ryuji::Vars = std::collections::HashMap::from([
+    let vars_max: ryuji::Vars = std::collections::HashMap::from([
       ("trees".to_string(), ryuji::VarValue::Vec(vec![
         ryuji::VarValue::String("mango".to_string()),
         ryuji::VarValue::String("oak".to_string()),
@@ -116,11 +259,11 @@ mod tests {
         ryuji::VarValue::String("palm".to_string()),
       ])),
     ]);
-    let rendered_if: String = renderer.render("[[ for:trees:tree:index_var:max_var ]][[ index_var ]]/[[ max_var ]] [[ tree ]][[ if:index_var:!max_var ]] [[ endif ]][[ endfor ]]".to_string(), &mut vars_max, None).unwrap();
+    let rendered_if: String = renderer.render("[[ for:trees:tree:index_var:max_var ]][[ index_var ]]/[[ max_var ]] [[ tree ]][[ if:index_var:!max_var ]] [[ endif ]][[ endfor ]]".to_string(), &vars_max, None).unwrap();
     assert_eq!(rendered_if, "0/3 mango 1/3 oak 2/3 redwood 3/3 palm");
 
     //another for loop with if statement test
-    let mut vars_if2: ryuji::Vars = std::collections::HashMap::from([
+    let vars_if2: ryuji::Vars = std::collections::HashMap::from([
       ("letters".to_string(), ryuji::VarValue::Vec(vec![
         ryuji::VarValue::HashMap(std::collections::HashMap::from([
           ("letter".to_string(), ryuji::VarValue::String("a".to_string())),
@@ -140,18 +283,700 @@ mod tests {
         ])),
       ])),
     ]);
-    let rendered_if2: String = renderer.render("[[ for:letters:letter ]][[ if:letter.show ]]<p>[[ letter.letter ]]</p>[[ endif ]][[ endfor ]]".to_string(), &mut vars_if2, None).unwrap();
+    let rendered_if2: String = renderer.render("[[ for:letters:letter ]][[ if:letter.show ]]<p>[[ letter.letter ]]</p>[[ endif ]][[ endfor ]]".to_string(), &vars_if2, None).unwrap();
     assert_eq!(rendered_if2, "<p>a</p><p>c</p>");
 
     //nested for loop test
-    let mut vars_nested: ryuji::Vars = std::collections::HashMap::from([
+    let vars_nested: ryuji::Vars = std::collections::HashMap::from([
       ("numbers".to_string(), ryuji::VarValue::Vec(vec![
         ryuji::VarValue::U32(1),
         ryuji::VarValue::U32(2),
         ryuji::VarValue::U32(3),
       ])),
     ]);
-    let rendered_nested: String = renderer.render("[[ for:numbers:i ]].[[ i ]].[[ for:numbers:j ]][[ j ]][[ endfor ]][[ endfor ]]".to_string(), &mut vars_nested, None).unwrap();
+    let rendered_nested: String = renderer.render("[[ for:numbers:i ]].[[ i ]].[[ for:numbers:j ]][[ j ]][[ endfor ]][[ endfor ]]".to_string(), &vars_nested, None).unwrap();
     assert_eq!(rendered_nested, ".1.123.2.123.3.123");
   }
+
+  #[test]
+  fn for_loop_scope_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("name".to_string(), ryuji::VarValue::String("outer".to_string())),
+      ("names".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("a".to_string()),
+        ryuji::VarValue::String("b".to_string()),
+      ])),
+    ]);
+    //loop variables shadow outer ones only inside of the loop
+    let rendered: String = renderer.render("[[ name ]] [[ for:names:name:i ]][[ name ]][[ i ]] [[ endfor ]][[ name ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered, "outer a0 b1 outer");
+    //inner loop variables shadow outer loop variables
+    let rendered_nested: String = renderer.render("[[ for:names:name ]][[ name ]]([[ for:names:name ]][[ name ]][[ endfor ]])[[ name ]] [[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_nested, "a(ab)a b(ab)b ");
+    //loop variables do not stick around after the loop, and the vars passed in are untouched
+    assert!(matches!(renderer.render("[[ for:names:item:index ]][[ endfor ]][[ item ]]".to_string(), &vars, None).unwrap_err().without_location(), ryuji::ErrorKind::VarNotFound(_)));
+    assert!(renderer.render("[[ for:names:item:index ]][[ endfor ]][[ index ]]".to_string(), &vars, None).is_err());
+    assert_eq!(vars.len(), 2);
+    assert!(vars.get("name") == Some(&ryuji::VarValue::String("outer".to_string())));
+  }
+
+  #[test]
+  fn for_hashmap_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("counts".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("rust".to_string(), ryuji::VarValue::U32(3)),
+        ("cooking".to_string(), ryuji::VarValue::U32(12)),
+        ("birds".to_string(), ryuji::VarValue::U32(7)),
+      ]))),
+      ("settings".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::new())),
+    ]);
+    //keys are always looped over in sorted order
+    let rendered: String = renderer.render("[[ for:counts:category:count:index:max ]][[ category ]]=[[ count ]][[ if:index:!max ]], [[ endif ]][[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered, "birds=7, cooking=12, rust=3");
+    let rendered_keys: String = renderer.render("[[ for:counts:category ]]<[[ category ]]>[[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_keys, "<birds><cooking><rust>");
+    let rendered_empty: String = renderer.render("[[ for:settings:key:value ]][[ key ]][[ empty ]]no settings[[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_empty, "no settings");
+    assert!(renderer.render("[[ for:counts:a:b:c:d:e ]][[ endfor ]]".to_string(), &vars, None).is_err());
+  }
+
+  #[test]
+  fn loop_var_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("tags".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("a".to_string()),
+        ryuji::VarValue::String("b".to_string()),
+        ryuji::VarValue::String("c".to_string()),
+      ])),
+      ("numbers".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::U32(1),
+        ryuji::VarValue::U32(2),
+      ])),
+      ("true".to_string(), ryuji::VarValue::Bool(true)),
+    ]);
+    let rendered: String = renderer.render("[[ for:tags:tag ]][[ loop.index ]]/[[ loop.index1 ]]/[[ loop.length ]] [[ tag ]][[ if:loop.first ]] first[[ endif ]][[ if:loop.last ]] last[[ else ]], [[ endif ]][[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered, "0/1/3 a first, 1/2/3 b, 2/3/3 c last");
+    let rendered_parity: String = renderer.render("[[ for:tags:tag ]]<li class=\"[[ if:loop.odd ]]odd[[ endif ]][[ if:loop.even ]]even[[ endif ]]\">[[ tag ]]</li>[[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_parity, "<li class=\"odd\">a</li><li class=\"even\">b</li><li class=\"odd\">c</li>");
+    //nested loops
+    let rendered_nested: String = renderer.render("[[ for:numbers ]][[ for:tags ]][[ loop.depth ]]:[[ loop.parent.index1 ]].[[ loop.index1 ]][[ if:loop.parent.last:true ]][[ if:loop.last ]]![[ endif ]][[ endif ]] [[ endfor ]][[ loop.depth ]] [[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_nested, "2:1.1 2:1.2 2:1.3 1 2:2.1 2:2.2 2:2.3! 1 ");
+    //outermost loop has no parent
+    assert!(renderer.render("[[ for:tags ]][[ loop.parent.index ]][[ endfor ]]".to_string(), &vars, None).is_err());
+  }
+
+  #[test]
+  fn for_empty_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("posts".to_string(), ryuji::VarValue::Vec(Vec::new())),
+      ("tags".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("a".to_string()),
+        ryuji::VarValue::String("b".to_string()),
+      ])),
+      ("empty".to_string(), ryuji::VarValue::String("not a marker".to_string())),
+    ]);
+    let rendered: String = renderer.render("<ul>[[ for:posts:post ]]<li>[[ post ]]</li>[[ forelse ]]<li>No posts yet</li>[[ endfor ]]</ul>".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered, "<ul><li>No posts yet</li></ul>");
+    //`[[ empty ]]` works the same way, and is not rendered when there are items
+    let rendered_tags: String = renderer.render("[[ for:tags:tag ]]#[[ tag ]][[ empty ]]no tags[[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_tags, "#a#b");
+    //nested, with the inner loop being empty
+    let rendered_nested: String = renderer.render("[[ for:tags:tag ]][[ tag ]]:[[ for:posts ]]x[[ empty ]]none[[ endfor ]];[[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_nested, "a:none;b:none;");
+    //outside of a for loop (or inside an if inside of one), `empty` is just a variable
+    let rendered_var: String = renderer.render("[[ empty ]][[ for:tags ]][[ if:tags ]][[ empty ]][[ endif ]][[ endfor ]]".to_string(), &vars, None).unwrap();
+    assert_eq!(rendered_var, "not a markernot a markernot a marker");
+    assert!(renderer.render("[[ for:posts ]]a[[ forelse ]]b[[ empty ]]c[[ endfor ]]".to_string(), &vars, None).is_err());
+  }
+
+  #[test]
+  fn component_props_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("site_name".to_string(), ryuji::VarValue::String("Blog".to_string())),
+      ("title".to_string(), ryuji::VarValue::String("Home".to_string())),
+      ("posts".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::HashMap(std::collections::HashMap::from([
+          ("title".to_string(), ryuji::VarValue::String("First <post>".to_string())),
+          ("slug".to_string(), ryuji::VarValue::String("first".to_string())),
+        ])),
+        ryuji::VarValue::HashMap(std::collections::HashMap::from([
+          ("title".to_string(), ryuji::VarValue::String("Second".to_string())),
+          ("slug".to_string(), ryuji::VarValue::String("second".to_string())),
+        ])),
+      ])),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ for:posts:post ]][[ component:card:title=post.title:slug=post.slug:featured=loop.first ]]\n[[ endfor ]]").unwrap(), "<a href=\"/posts/first\">First &lt;post&gt;</a> (featured)\n<a href=\"/posts/second\">Second</a>\n");
+    //props can be literals, and shadow the variables of where the component is used
+    assert_eq!(render("[[ component:site-title ]] [[ component:site-title:title=\"About: me\" ]] [[ title ]]").unwrap(), "Blog: Home Blog: About: me Home");
+    //with `only`, the component cannot see anything but its props
+    assert_eq!(render("[[ component:site-title:site_name=\"Shop\":title=title:only ]]").unwrap(), "Shop: Home");
+    assert!(render("[[ component:site-title:title=title:only ]]").is_err());
+    //bad props
+    assert!(render("[[ component:card:title ]]").is_err());
+    assert!(render("[[ component:card:post.title=title ]]").is_err());
+    assert!(render("[[ component:card:title=title:title=title ]]").is_err());
+    assert!(render("[[ component:card:title= ]]").is_err());
+  }
+
+  #[test]
+  fn component_slots_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("name".to_string(), ryuji::VarValue::String("<dave>".to_string())),
+      ("items".to_string(), ryuji::VarValue::Vec(vec![ryuji::VarValue::U32(1), ryuji::VarValue::U32(2)])),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    //inner content is rendered where the component is used, and is not sanitized again
+    assert_eq!(render("[[ component:box ]]<b>[[ name ]]</b>[[ endcomponent ]]").unwrap(), "<div class=\"box\"><b>&lt;dave&gt;</b></div>");
+    //the inline form still works, and has no slot
+    assert_eq!(render("[[ component:box ]] [[ component:box ]]").unwrap(), "<div class=\"box\"></div> <div class=\"box\"></div>");
+    //inline components directly inside of a block component
+    //`[[ endcomponent ]]` goes with the closest `component:`, so inline components directly inside of a block component need one too
+    assert_eq!(render("[[ component:box ]]A[[ component:icon ]][[ endcomponent ]]B[[ endcomponent ]]").unwrap(), "<div class=\"box\">A<i/>B</div>");
+    assert_eq!(render("[[ component:icon ]][[ component:box ]]A[[ endcomponent ]][[ component:icon ]]").unwrap(), "<i/><div class=\"box\">A</div><i/>");
+    assert_eq!(render("[[ component:box ]][[ if:items ]][[ component:icon ]][[ else ]][[ component:icon ]][[ endif ]][[ endcomponent ]]").unwrap(), "<div class=\"box\"><i/></div>");
+    //otherwise the content would be lost, since the icon does not use `slot`
+    assert!(render("[[ component:box ]]A[[ component:icon ]]B[[ endcomponent ]]").is_err());
+    //nested, and in for loops
+    assert_eq!(render("[[ component:box ]][[ for:items:item ]][[ component:box ]][[ item ]][[ endcomponent ]][[ endfor ]][[ endcomponent ]]").unwrap(), "<div class=\"box\"><div class=\"box\">1</div><div class=\"box\">2</div></div>");
+    assert_eq!(render("[[ component:box ]][[ if:items ]][[ component:box ]][[ endif ]][[ endcomponent ]]").unwrap(), "<div class=\"box\"><div class=\"box\"></div></div>");
+    //named slots
+    assert_eq!(render("[[ component:modal:only ]][[ slot:header ]]Hi [[ name ]][[ endslot ]]line 1\nline 2[[ endcomponent ]]").unwrap(), "<div class=\"modal\">\n  <h2>Hi &lt;dave&gt;</h2>\n  line 1\n  line 2\n</div>");
+    assert!(render("[[ component:modal ]]no header[[ endcomponent ]]").is_err());
+    assert!(render("[[ component:modal ]][[ slot:header ]]a[[ endslot ]][[ slot:header ]]b[[ endslot ]][[ endcomponent ]]").is_err());
+    assert!(render("[[ component:modal ]][[ slot:header ]]a[[ endcomponent ]]").is_err());
+    assert!(render("[[ slot:header ]]a[[ endslot ]]").is_err());
+    assert!(render("[[ endcomponent ]]").is_err());
+    assert_eq!(render("[[ component:box ]][[ endcomponent ]]").unwrap(), "<div class=\"box\"></div>");
+    assert!(render("[[ component:box ]][[ if:name ]][[ endcomponent ]][[ endif ]]").is_err());
+    //`slot` and `slots` are only set for the block form, otherwise they are normal variables
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("slot".to_string(), ryuji::VarValue::String("<global>".to_string())),
+      ("slots".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("header".to_string(), ryuji::VarValue::String("global header".to_string())),
+      ]))),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ slot ]] [[ slots.header ]]").unwrap(), "&lt;global&gt; global header");
+    assert_eq!(render("[[ component:box ]][[ component:modal ]]").unwrap(), "<div class=\"box\"><global></div><div class=\"modal\">\n  <h2>global header</h2>\n  <global>\n</div>");
+    assert_eq!(render("[[ component:box ]]inner[[ endcomponent ]]").unwrap(), "<div class=\"box\">inner</div>");
+  }
+
+  #[test]
+  fn extends_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String("Hello".to_string())),
+      ("body".to_string(), ryuji::VarValue::String("<p>hi</p>".to_string())),
+      ("parent".to_string(), ryuji::VarValue::String("mom".to_string())),
+    ]);
+    //blocks override the blocks of the template they extend, and `[[ parent ]]` renders the overridden block
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "<html><head><title>Hello - Site</title></head>\n<body><main></main></body></html>");
+    //multiple levels
+    assert_eq!(renderer.render_template("article".to_string(), &vars, None).unwrap(), "<html><head><title>Hello - Site</title></head>\n<body><main><article><p>hi</p></article></main></body></html>");
+    assert_eq!(renderer.load_template("article".to_string()).unwrap().extends(), Some(&"page".to_string()));
+    //templates that do not extend anything just render their blocks
+    assert_eq!(renderer.render("[[ block:a ]]a[[ endblock ]] [[ parent ]]".to_string(), &vars, None).unwrap(), "a mom");
+    assert!(renderer.render("[[ block:a ]][[ parent ]][[ endblock ]]".to_string(), &vars, None).is_err());
+    //blocks inside of a component's content
+    assert_eq!(renderer.render_template("boxed".to_string(), &vars, None).unwrap(), "<div class=\"box\">default</div>");
+    assert_eq!(renderer.render_template("boxed-child".to_string(), &vars, None).unwrap(), "<div class=\"box\">child, default</div>");
+    assert_eq!(renderer.render("[[ component:modal ]][[ slot:header ]][[ block:a ]]a[[ endblock ]][[ endslot ]][[ endcomponent ]]".to_string(), &vars, None).unwrap(), "<div class=\"modal\">\n  <h2>a</h2>\n  \n</div>");
+    //bad syntax, and templates extending each other
+    assert!(renderer.render("a[[ title ]][[ extends:base ]]".to_string(), &vars, None).is_err());
+    assert!(renderer.render("[[ block:a ]][[ endblock ]][[ block:a ]][[ endblock ]]".to_string(), &vars, None).is_err());
+    assert!(renderer.render("[[ block:a ]]".to_string(), &vars, None).is_err());
+    assert!(renderer.render("[[ endblock ]]".to_string(), &vars, None).is_err());
+    let error: ryuji::ErrorKind = renderer.render_template("loop-a".to_string(), &vars, None).unwrap_err();
+    assert!(error.to_string().contains("loop-a.html -> loop-b.html -> loop-a.html"));
+  }
+
+  #[test]
+  fn macro_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("name".to_string(), ryuji::VarValue::String("dave".to_string())),
+      ("url".to_string(), ryuji::VarValue::String("/about".to_string())),
+      ("tags".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("rust".to_string()),
+        ryuji::VarValue::String("<html>".to_string()),
+      ])),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ macro:bold:text ]]<b>[[ text ]]</b>[[ endmacro ]][[ call:bold:name ]] [[ call:bold:\"hi\" ]]").unwrap(), "<b>dave</b> <b>hi</b>");
+    //macros can be called before they are defined, and see the original vars, but not the variables of where they are called
+    assert_eq!(render("[[ call:greet ]][[ macro:greet ]]hi [[ name ]][[ endmacro ]]").unwrap(), "hi dave");
+    assert!(render("[[ macro:show ]][[ tag ]][[ endmacro ]][[ for:tags:tag ]][[ call:show ]][[ endfor ]]").is_err());
+    //imported macros, which can call other macros from the same file
+    assert_eq!(render("[[ import:macros ]][[ for:tags:tag ]][[ call:pill:tag:url ]][[ endfor ]]").unwrap(), "<a class=\"pill\" href=\"/about\">rust</a><a class=\"pill\" href=\"/about\">&lt;html&gt;</a>");
+    assert_eq!(render("[[ import:macros ]][[ call:pills:tags ]]").unwrap(), "<a class=\"pill\" href=\"#\">rust</a><a class=\"pill\" href=\"#\">&lt;html&gt;</a>");
+    //macros in the template override imported ones
+    assert_eq!(render("[[ import:macros ]][[ macro:pill:a:b ]][[ a ]][[ endmacro ]][[ call:pill:1:2 ]]").unwrap(), "1");
+    //wrong number of args, missing macros, and bad syntax
+    assert!(render("[[ import:macros ]][[ call:pill:name ]]").is_err());
+    assert!(render("[[ call:pill:name:url ]]").is_err());
+    assert!(render("[[ if:name ]][[ macro:a ]][[ endmacro ]][[ endif ]]").is_err());
+    assert!(render("[[ macro:a:b:b ]][[ endmacro ]]").is_err());
+    assert!(render("[[ macro:a ]][[ endmacro ]][[ macro:a ]][[ endmacro ]]").is_err());
+    assert!(render("[[ macro:a ]]").is_err());
+    assert!(render("[[ macro:a ]][[ block:b ]][[ endblock ]][[ endmacro ]]").is_err());
+    //recursion
+    assert!(matches!(render("[[ macro:forever ]][[ call:forever ]][[ endmacro ]][[ call:forever ]]").unwrap_err().without_location(), ryuji::ErrorKind::RecursionCycle(_)));
+  }
+
+  #[test]
+  fn recursion_test() {
+    let mut renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    //comment thread 8 levels deep
+    let mut comment: ryuji::VarValue = ryuji::VarValue::HashMap(std::collections::HashMap::from([
+      ("text".to_string(), ryuji::VarValue::String("8".to_string())),
+      ("replies".to_string(), ryuji::VarValue::Vec(Vec::new())),
+    ]));
+    for level in (1..8).rev() {
+      comment = ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("text".to_string(), ryuji::VarValue::String(level.to_string())),
+        ("replies".to_string(), ryuji::VarValue::Vec(vec![comment])),
+      ]));
+    }
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("comment".to_string(), comment),
+    ]);
+    let error: ryuji::ErrorKind = renderer.render("[[ component:thread:comment=comment:only ]]".to_string(), &vars, None).unwrap_err();
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::RecursionTooDeep(5, chain) if chain.len() == 8 && chain[0] == "(unnamed template)" && chain[7] == "component:thread"));
+    renderer.max_recursion_depth = 10;
+    assert_eq!(renderer.render("[[ component:thread:comment=comment:only ]]".to_string(), &vars, None).unwrap(), "<li>1<ul><li>2<ul><li>3<ul><li>4<ul><li>5<ul><li>6<ul><li>7<ul><li>8</li></ul></li></ul></li></ul></li></ul></li></ul></li></ul></li></ul></li>");
+    //components without props see the variables of where they are used, so those count too
+    let mut comment: ryuji::VarValue = ryuji::VarValue::HashMap(std::collections::HashMap::from([
+      ("text".to_string(), ryuji::VarValue::String("3".to_string())),
+      ("replies".to_string(), ryuji::VarValue::Vec(Vec::new())),
+    ]));
+    for level in (1..3).rev() {
+      comment = ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("text".to_string(), ryuji::VarValue::String(level.to_string())),
+        ("replies".to_string(), ryuji::VarValue::Vec(vec![comment])),
+      ]));
+    }
+    let reply_vars: ryuji::Vars = std::collections::HashMap::from([
+      ("comment".to_string(), comment),
+    ]);
+    assert_eq!(renderer.render("<ul>[[ component:reply-thread ]]</ul>".to_string(), &reply_vars, None).unwrap(), "<ul><li>1<li>2<li>3</li></li></li></ul>");
+    //cycles are caught no matter the max depth
+    renderer.max_recursion_depth = 1000;
+    let error: ryuji::ErrorKind = renderer.render("[[ component:cycle-a ]]".to_string(), &vars, None).unwrap_err();
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::RecursionCycle(chain) if chain == &["component:cycle-a", "component:cycle-b", "component:cycle-a"]));
+    assert!(error.to_string().contains("component:cycle-a -> component:cycle-b -> component:cycle-a"));
+    //even through a for loop, where the loop variables are different every level
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("items".to_string(), ryuji::VarValue::Vec(vec![ryuji::VarValue::U32(1)])),
+    ]);
+    let error: ryuji::ErrorKind = renderer.render("[[ component:loop-cycle-a ]]".to_string(), &vars, None).unwrap_err();
+    //(the first `loop-cycle-a` is not inside of the for loop yet, so it is from the first `loop-cycle-b`)
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::RecursionCycle(chain) if chain == &["component:loop-cycle-b", "component:loop-cycle-a", "component:loop-cycle-b"]));
+  }
+
+  #[test]
+  fn loader_test() {
+    let memory_loader: loader::MemoryLoader = loader::MemoryLoader::new(std::collections::HashMap::from([
+      ("page.html".to_string(), "[[ extends:base ]][[ block:content ]]<p>[[ name ]]</p>[[ endblock ]]".to_string()),
+      ("base.html".to_string(), "<body>[[ block:content ]][[ endblock ]]</body>".to_string()),
+      ("parts/greeting.html".to_string(), "hi [[ name ]]".to_string()),
+    ]));
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("name".to_string(), ryuji::VarValue::String("dave".to_string())),
+    ]);
+    //templates, ones they extend, and components all come from the loader
+    let mut renderer: ryuji::Renderer = ryuji::Renderer::with_loader(memory_loader, "parts".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "<body><p>dave</p></body>");
+    assert_eq!(renderer.render("[[ component:greeting ]]".to_string(), &vars, None).unwrap(), "hi dave");
+    //falls back to the next loader if a template is not found
+    let mut memory_loader: loader::MemoryLoader = loader::MemoryLoader::new(std::collections::HashMap::new());
+    memory_loader.insert("base.html".to_string(), "<main>[[ block:content ]][[ endblock ]]</main>".to_string());
+    renderer = ryuji::Renderer::with_loader(loader::ChainLoader::new(vec![
+      Box::new(memory_loader),
+      Box::new(loader::FileSystemLoader::new("templates".to_string())),
+    ]), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "<main><main></main></main>");
+    assert!(loader::ChainLoader::new(Vec::new()).load("page.html").is_err());
+  }
+
+  #[test]
+  fn missing_template_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::new();
+    let error: ryuji::ErrorKind = renderer.render_template("missing".to_string(), &vars, None).unwrap_err();
+    assert!(matches!(error, ryuji::ErrorKind::TemplateNotFound(path, ryuji::TemplateKind::Page) if path == "templates/missing.html"));
+    //components, and templates that are extended, say where they were used
+    let error: ryuji::ErrorKind = renderer.render("a\n[[ component:top-headr ]]".to_string(), &vars, None).unwrap_err();
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::TemplateNotFound(path, ryuji::TemplateKind::Component) if path == "templates/components/top-headr.html"));
+    assert_eq!(error.location().unwrap().line, 2);
+    assert_eq!(error.to_string().lines().next().unwrap(), "Component 'templates/components/top-headr.html' not found");
+    let error: ryuji::ErrorKind = renderer.render("[[ extends:missing ]]".to_string(), &vars, None).unwrap_err();
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::TemplateNotFound(path, ryuji::TemplateKind::Page) if path == "templates/missing.html"));
+    //other io errors, like trying to read a directory
+    let error: ryuji::ErrorKind = renderer.render_template("components/.".to_string(), &vars, None).unwrap_err();
+    assert!(matches!(error, ryuji::ErrorKind::Io(path, ryuji::TemplateKind::Page, _) if path == "templates/components/."));
+  }
+
+  //counts how many times templates are loaded
+  struct CountingLoader {
+    loader: loader::MemoryLoader,
+    loads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+  }
+
+  impl loader::TemplateLoader for CountingLoader {
+    fn load(&self, name: &str) -> std::io::Result<String> {
+      self.loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+      self.loader.load(name)
+    }
+  }
+
+  #[test]
+  fn cache_test() {
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("names".to_string(), ryuji::VarValue::Vec(vec![ryuji::VarValue::String("a".to_string()), ryuji::VarValue::String("b".to_string())])),
+    ]);
+    let loads: std::sync::Arc<std::sync::atomic::AtomicUsize> = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counting_loader: CountingLoader = CountingLoader {
+      loader: loader::MemoryLoader::new(std::collections::HashMap::from([
+        ("page.html".to_string(), "[[ for:names:name ]][[ component:thanks ]][[ endfor ]]".to_string()),
+        ("components/thanks.html".to_string(), "thanks [[ name ]]! ".to_string()),
+      ])),
+      loads: loads.clone(),
+    };
+    let mut renderer: ryuji::Renderer = ryuji::Renderer::with_loader(counting_loader, "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let loads = || loads.load(std::sync::atomic::Ordering::SeqCst);
+    //no caching by default, so the component is loaded for every loop
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "thanks a! thanks b! ");
+    assert_eq!(loads(), 3);
+    renderer.cache_mode = ryuji::CacheMode::Forever;
+    renderer.render_template("page".to_string(), &vars, None).unwrap();
+    renderer.render_template("page".to_string(), &vars, None).unwrap();
+    assert_eq!(loads(), 5);
+    renderer.clear_cache();
+    renderer.render_template("page".to_string(), &vars, None).unwrap();
+    assert_eq!(loads(), 7);
+    //memory loader cannot tell when templates were modified, so they are always reloaded
+    renderer.cache_mode = ryuji::CacheMode::CheckModified;
+    renderer.render_template("page".to_string(), &vars, None).unwrap();
+    assert_eq!(loads(), 10);
+  }
+
+  #[test]
+  fn cache_modified_test() {
+    let templates_dir: std::path::PathBuf = std::env::temp_dir().join(format!("ryuji_cache_test_{}", std::process::id()));
+    std::fs::create_dir_all(&templates_dir).unwrap();
+    let path: std::path::PathBuf = templates_dir.join("page.html");
+    let write = |template_contents: &str, seconds: u64| {
+      std::fs::write(&path, template_contents).unwrap();
+      //set the modified time, since writes in the same instant might have the same modified time
+      std::fs::File::options().write(true).open(&path).unwrap().set_modified(std::time::UNIX_EPOCH+std::time::Duration::from_secs(seconds)).unwrap();
+    };
+    let mut renderer: ryuji::Renderer = ryuji::Renderer::new(templates_dir.to_str().unwrap().to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::new();
+    renderer.cache_mode = ryuji::CacheMode::Forever;
+    write("first", 1000);
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "first");
+    write("second", 2000);
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "first");
+    renderer.cache_mode = ryuji::CacheMode::CheckModified;
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "second");
+    //not modified, so the cached one is used
+    std::fs::write(&path, "third").unwrap();
+    std::fs::File::options().write(true).open(&path).unwrap().set_modified(std::time::UNIX_EPOCH+std::time::Duration::from_secs(2000)).unwrap();
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "second");
+    write("fourth", 3000);
+    assert_eq!(renderer.render_template("page".to_string(), &vars, None).unwrap(), "fourth");
+    std::fs::remove_dir_all(&templates_dir).unwrap();
+  }
+
+  #[test]
+  fn template_path_test() {
+    assert!(ryuji::Renderer::check_template_path_legality("post").is_ok());
+    assert!(ryuji::Renderer::check_template_path_legality("themes/dark/post.html").is_ok());
+    assert!(ryuji::Renderer::check_template_path_legality("./post..html").is_ok());
+    for template_name in ["../post", "themes/../../post", "themes/..", "..\\post", "/etc/passwd", "\\server\\post", "C:\\post", ""] {
+      assert!(matches!(ryuji::Renderer::check_template_path_legality(template_name), Err(ryuji::ErrorKind::IllegalTemplatePath(_))), "{}", template_name);
+    }
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::new();
+    assert!(matches!(renderer.render_template("../src/lib.rs".to_string(), &vars, None).unwrap_err(), ryuji::ErrorKind::IllegalTemplatePath(_)));
+    assert!(matches!(renderer.render_template("/etc/passwd".to_string(), &vars, None).unwrap_err(), ryuji::ErrorKind::IllegalTemplatePath(_)));
+    //components, and templates that are extended or imported
+    assert!(matches!(renderer.render("[[ component:.. ]]".to_string(), &vars, None).unwrap_err().without_location(), ryuji::ErrorKind::IllegalTemplatePath(_)));
+    assert!(matches!(renderer.render("[[ extends:.. ]]".to_string(), &vars, None).unwrap_err().without_location(), ryuji::ErrorKind::IllegalTemplatePath(_)));
+    assert!(matches!(renderer.render("[[ import:.. ]]".to_string(), &vars, None).unwrap_err().without_location(), ryuji::ErrorKind::IllegalTemplatePath(_)));
+  }
+
+  //fails after writing some bytes
+  struct FailingWriter {
+    written: Vec<u8>,
+  }
+
+  impl std::io::Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      if self.written.len() > 8 {
+        return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "connection closed"));
+      }
+      self.written.extend_from_slice(buf);
+      Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn render_to_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String("Hello".to_string())),
+      ("lines".to_string(), ryuji::VarValue::String("<li>a</li>\n<li>b</li>".to_string())),
+    ]);
+    //same output as `render`, including indentation inside of components
+    let template: ryuji::Template = ryuji::Template::new("  <p>[[ title ]]</p>\n  [[ component:list:slot=lines ]] [[ lines ]]".to_string(), None).unwrap();
+    let rendered: String = template.render(&renderer, &vars, None).unwrap();
+    assert_eq!(rendered, "  <p>Hello</p>\n  <ul>\n  <li>a</li>\n  <li>b</li>\n</ul> &lt;li&gt;a&lt;/li&gt;\n&lt;li&gt;b&lt;/li&gt;");
+    let mut bytes: Vec<u8> = Vec::new();
+    template.render_to(&mut bytes, &renderer, &vars, None).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), rendered);
+    let mut fmt_rendered: String = "already here\n".to_string();
+    template.render_to_fmt(&mut fmt_rendered, &renderer, &vars, None).unwrap();
+    assert_eq!(fmt_rendered, "already here\n".to_string()+&rendered);
+    let mut bytes: Vec<u8> = Vec::new();
+    renderer.render_template_to(&mut bytes, "page".to_string(), &vars, None).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), renderer.render_template("page".to_string(), &vars, None).unwrap());
+    //write errors are returned
+    let mut failing_writer: FailingWriter = FailingWriter {
+      written: Vec::new(),
+    };
+    let error: ryuji::ErrorKind = template.render_to(&mut failing_writer, &renderer, &vars, None).unwrap_err();
+    assert!(matches!(error, ryuji::ErrorKind::Output(io_error) if io_error.kind() == std::io::ErrorKind::BrokenPipe));
+    assert_eq!(failing_writer.written, b"  <p>Hello");
+  }
+
+  #[test]
+  fn filter_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String("Héllo <World>".to_string())),
+      ("empty".to_string(), ryuji::VarValue::String("".to_string())),
+      ("things".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("a".to_string()),
+        ryuji::VarValue::String("b".to_string()),
+      ])),
+      ("no_things".to_string(), ryuji::VarValue::Vec(Vec::new())),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    //filters are applied before sanitizing
+    assert_eq!(render("[[ title|upper ]] [[ html:title|lower ]]").unwrap(), "HÉLLO &lt;WORLD&gt; héllo <world>");
+    assert_eq!(render("[[ title|upper|truncate:5 ]] [[ title|truncate:2:\"~\" ]] [[ title|truncate:50 ]]").unwrap(), "HÉLLO... Hé~ Héllo &lt;World&gt;");
+    assert_eq!(render("[[ empty|default:\"none\" ]] [[ no_things|default:title|truncate:(1) ]]").unwrap(), "none H...");
+    //in `if:` and `for:` args
+    assert_eq!(render("[[ if:title|truncate:5:\"\" == \"Héllo\" ]]a[[ endif ]][[ if:not empty|default:\"b\" ]]a[[ else ]]b[[ endif ]]").unwrap(), "ab");
+    assert_eq!(render("[[ for:(no_things|default:things):thing ]][[ thing|upper ]][[ endfor ]]").unwrap(), "AB");
+    //errors should say where the problem is
+    let error: ryuji::ErrorKind = render("a\n[[ title|nope ]]").unwrap_err();
+    assert_eq!(error.location().unwrap().line, 2);
+    assert!(error.without_location().to_string().contains("Filter `nope` does not exist"));
+    assert!(render("[[ things|upper ]]").is_err());
+    assert!(render("[[ title|truncate ]]").is_err());
+    assert!(render("[[ if:title| ]][[ endif ]]").is_err());
+    assert_eq!(render("[[ title| ]]").unwrap(), "[[ title| ]]");
+  }
+
+  #[test]
+  fn register_test() {
+    let mut renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    renderer.register_filter("price", |value: &ryuji::VarValue, args: &[ryuji::VarValue]| {
+      match (value, args.first()) {
+        (ryuji::VarValue::U32(cents), Some(currency)) => Ok(ryuji::VarValue::String(format!("{}.{:02} {}", cents/100, cents%100, currency))),
+        _ => Err(ryuji::ErrorKind::BadArgument("`price` filter needs cents and a currency".to_string())),
+      }
+    }).unwrap();
+    //replaces the built in filter
+    renderer.register_filter("upper", |_: &ryuji::VarValue, _: &[ryuji::VarValue]| Ok(ryuji::VarValue::String("shouting".to_string()))).unwrap();
+    renderer.register_function("route", |args: &[ryuji::VarValue]| {
+      Ok(ryuji::VarValue::String(format!("/{}", args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().join("/"))))
+    }).unwrap();
+    renderer.register_function("now", |_: &[ryuji::VarValue]| Ok(ryuji::VarValue::U32(2024))).unwrap();
+    assert!(renderer.register_filter("bad name", |value: &ryuji::VarValue, _: &[ryuji::VarValue]| Ok(value.clone())).is_err());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("cents".to_string(), ryuji::VarValue::U32(1999)),
+      ("slug".to_string(), ryuji::VarValue::String("hello-world".to_string())),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ cents|price:\"USD\" ]] [[ slug|upper ]]").unwrap(), "19.99 USD shouting");
+    assert_eq!(render("[[ route(\"posts\", slug) ]] [[ route() ]] [[ route(\"year\", now())|truncate:6 ]]").unwrap(), "/posts/hello-world / /year/...");
+    assert_eq!(render("[[ if:now() > 2000 and route(slug) == \"/hello-world\" ]]a[[ endif ]]").unwrap(), "a");
+    //errors should say where the problem is
+    let error: ryuji::ErrorKind = render("a\nb [[ slug|price:\"USD\" ]]").unwrap_err();
+    assert_eq!((error.location().unwrap().line, error.location().unwrap().column), (2, 3));
+    assert!(error.without_location().to_string().contains("`price` filter needs cents and a currency"));
+    let error: ryuji::ErrorKind = render("[[ if:missing() ]][[ endif ]]").unwrap_err();
+    assert!(error.location().is_some());
+    assert!(error.without_location().to_string().contains("Function `missing` does not exist"));
+    assert!(render("[[ if:route(slug ]][[ endif ]]").is_err());
+    assert!(render("[[ html:route(,) ]]").is_err());
+  }
+
+  #[test]
+  fn string_filter_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String("  the QUICK brown-fox  ".to_string())),
+      ("heading".to_string(), ryuji::VarValue::String("Ça va? Rust & <Templates>!".to_string())),
+      ("bio".to_string(), ryuji::VarValue::String("<b>Hi</b> \"there\"\nbye".to_string())),
+      ("views".to_string(), ryuji::VarValue::U32(1500)),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None).unwrap();
+    assert_eq!(render("[[ title|trim|upper ]]|[[ title|trim|lower ]]|[[ title|title ]]"), "THE QUICK BROWN-FOX|the quick brown-fox|  The Quick Brown-Fox  ");
+    assert_eq!(render("[[ title|trim|truncate:9 ]]|[[ title|replace:\"o\":\"0\" ]]|[[ views|replace:\"5\":\"4\" ]]"), "the QUICK...|  the QUICK br0wn-f0x  |1400");
+    //the end is added on top of the max chars
+    assert_eq!(render("[[ views|truncate:0 ]]|[[ views|truncate:0:\"\" ]]|[[ views|truncate:4 ]]"), "...||1500");
+    assert_eq!(render("[[ heading|slugify ]]|[[ title|slugify ]]|[[ heading|urlencode ]]"), "ça-va-rust-templates|the-quick-brown-fox|%C3%87a%20va%3F%20Rust%20%26%20%3CTemplates%3E%21");
+    assert_eq!(render("[[ heading|slugify|urlencode ]]"), "%C3%A7a-va-rust-templates");
+    assert_eq!(render("[[ bio|striptags ]]|[[ title|wordcount ]]|[[ heading|wordcount ]]"), "Hi \"there\"\nbye|3|5");
+    assert_eq!(render("[[ html:bio|nl2br ]]"), "&lt;b&gt;Hi&lt;/b&gt; &quot;there&quot;<br>\nbye");
+    assert_eq!(render("[[ html:heading|escape ]]|[[ html:bio|escape_js ]]"), "Ça va? Rust &amp; &lt;Templates&gt;!|\\u003Cb\\u003EHi\\u003C/b\\u003E \\\"there\\\"\\nbye");
+    assert!(renderer.render("[[ title|replace:\"\":\"a\" ]]".to_string(), &vars, None).is_err());
+    assert!(renderer.render("[[ title|trim:1 ]]".to_string(), &vars, None).is_err());
+  }
+
+  #[test]
+  fn collection_filter_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let post = |title: &str, views: u32| ryuji::VarValue::HashMap(std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String(title.to_string())),
+      ("stats".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("views".to_string(), ryuji::VarValue::U32(views)),
+      ]))),
+    ]));
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("tags".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::String("rust".to_string()),
+        ryuji::VarValue::String("web".to_string()),
+        ryuji::VarValue::String("rust".to_string()),
+        ryuji::VarValue::String("blog".to_string()),
+      ])),
+      ("numbers".to_string(), ryuji::VarValue::Vec(vec![ryuji::VarValue::U32(3), ryuji::VarValue::F64(1.5), ryuji::VarValue::U32(2)])),
+      ("posts".to_string(), ryuji::VarValue::Vec(vec![post("b", 20), post("a", 300), post("c", 4)])),
+      ("settings".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("theme".to_string(), ryuji::VarValue::String("dark".to_string())),
+      ]))),
+      ("nothing".to_string(), ryuji::VarValue::Vec(Vec::new())),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    assert_eq!(render("[[ tags|length ]] [[ settings|length ]] [[ tags|first|length ]] [[ nothing|length ]]").unwrap(), "4 1 4 0");
+    assert_eq!(render("[[ tags|join:\", \" ]]|[[ tags|unique|join ]]|[[ tags|first ]] [[ tags|last ]]").unwrap(), "rust, web, rust, blog|rustwebblog|rust blog");
+    assert_eq!(render("[[ tags|reverse|join:\" \" ]]|[[ tags|sort|unique|join:\" \" ]]|[[ numbers|sort|join:\" \" ]]|[[ tags|last|reverse ]]").unwrap(), "blog rust web rust|blog rust web|1.5 2 3|golb");
+    assert_eq!(render("[[ tags|slice:1:3|join:\" \" ]]|[[ tags|slice:2|join:\" \" ]]|[[ tags|slice:0:10|length ]]|[[ tags|slice:5:2|length ]]|[[ tags|first|slice:1:3 ]]").unwrap(), "web rust|rust blog|4|0|us");
+    assert_eq!(render("[[ posts|map:\"title\"|join:\",\" ]]|[[ posts|sort_by:\"title\"|map:\"title\"|join ]]|[[ posts|sort_by:\"stats.views\"|map:\"stats.views\"|join:\",\" ]]").unwrap(), "b,a,c|abc|4,20,300");
+    //attribute names can be written without quotes
+    assert_eq!(render("[[ posts|sort_by:title|map:title|join ]]|[[ posts|sort_by:stats.views|map:stats.views|join:\",\" ]]").unwrap(), "abc|4,20,300");
+    //as the source of a `for:` loop
+    assert_eq!(render("[[ for:(posts|sort_by:title|reverse):post ]][[ post.title ]][[ endfor ]]").unwrap(), "cba");
+    assert_eq!(render("[[ for:(posts|sort_by:\"stats.views\"|reverse|slice:0:2):post ]][[ post.title ]][[ endfor ]]").unwrap(), "ab");
+    assert_eq!(render("[[ for:tags|unique:tag:index ]][[ index ]][[ tag ]][[ endfor ]]").unwrap(), "0rust1web2blog");
+    assert_eq!(render("[[ if:tags|length > 3 and not nothing|length ]]a[[ endif ]]").unwrap(), "a");
+    assert!(render("[[ nothing|first ]]").is_err());
+    assert!(render("[[ settings|join ]]").is_err());
+    assert!(render("[[ posts|join ]]").is_err());
+    assert!(render("[[ posts|map:\"missing\" ]]").is_err());
+    assert!(render("[[ tags|sort_by:\"title\" ]]").is_err());
+    assert!(render("[[ posts|map:missing ]]").is_err());
+    let error: ryuji::ErrorKind = render("[[ posts|sort ]]").unwrap_err();
+    assert!(error.without_location().to_string().contains("`sort` filter cannot compare hashmap and hashmap"));
+  }
+
+  #[test]
+  fn default_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("post".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+        ("title".to_string(), ryuji::VarValue::String("Hello".to_string())),
+        ("summary".to_string(), ryuji::VarValue::String("".to_string())),
+        ("author".to_string(), ryuji::VarValue::HashMap(std::collections::HashMap::from([
+          ("name".to_string(), ryuji::VarValue::String("dave".to_string())),
+        ]))),
+      ]))),
+    ]);
+    let render = |template: &str| renderer.render(template.to_string(), &vars, None);
+    //`??` is used for missing variables, but not falsy ones
+    assert_eq!(render("[[ post.subtitle ?? \"Untitled\" ]]|[[ post.title ?? \"Untitled\" ]]|[[ post.summary ?? \"none\" ]]|[[ missing.a ?? missing ?? 5 ]]").unwrap(), "Untitled|Hello||5");
+    assert_eq!(render("[[ post.subtitle ?? post.title|upper ]]|[[ (post.subtitle ?? post.title)|truncate:2 ]]").unwrap(), "HELLO|He...");
+    //optional lookups are undefined instead of an error, which renders as nothing and is falsy
+    assert_eq!(render("[[ post?.subtitle ]]|[[ post?.subtitle?.text ]]|[[ post?.subtitle.text ?? \"a\" ]]|[[ post.author?.name ]]|[[ post?.subtitle|upper ?? \"b\" ]]|[[ post?.subtitle|default:\"c\" ]]").unwrap(), "||a|dave|b|c");
+    assert!(render("[[ post.subtitle ]]").is_err());
+    assert!(render("[[ missing.subtitle ]]").is_err());
+    //including when the first part is missing
+    assert_eq!(render("[[ missing?.subtitle ]]|[[ missing?.a.b ?? \"a\" ]]|[[ if:missing?.a ]]b[[ else ]]c[[ endif ]]|[[ missing?.a is defined ]]").unwrap(), "|a|c|false");
+    assert!(render("[[ post.author.name?.first ]]").unwrap().is_empty());
+    assert_eq!(render("[[ if:post?.subtitle ]]a[[ else ]]b[[ endif ]][[ for:post?.tags:tag ]][[ tag ]][[ empty ]]no tags[[ endfor ]]").unwrap(), "bno tags");
+    //checking if variables are defined
+    assert_eq!(render("[[ if:post.subtitle is defined ]]a[[ endif ]][[ if:post.title is defined ]]b[[ endif ]][[ if:missing.a is not defined ]]c[[ endif ]][[ if:post?.subtitle exists ]]d[[ endif ]][[ if:post.summary exists and not post.summary ]]e[[ endif ]]").unwrap(), "bce");
+    assert_eq!(render("[[ post.subtitle is defined ]] [[ post.author exists ]]").unwrap(), "false true");
+    //other errors are not hidden
+    assert!(render("[[ post.author|upper ?? \"a\" ]]").is_err());
+    assert!(render("[[ if:post.title|nope is defined ]][[ endif ]]").is_err());
+    assert!(render("[[ if:post.title is ]][[ endif ]]").is_err());
+    assert!(render("[[ if:post.title ?? ]][[ endif ]]").is_err());
+    assert!(render("[[ if:post.title? ]][[ endif ]]").is_err());
+    //not valid expressions, so left as text
+    assert_eq!(render("[[ post.title is ]] [[ post.title? ]]").unwrap(), "[[ post.title is ]] [[ post.title? ]]");
+  }
+
+  #[test]
+  fn compiled_template_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let template: ryuji::Template = ryuji::Template::new("<h1>[[ title ]]</h1>[[ if:show ]]<ul>[[ for:items:item ]]<li>[[ item ]]</li>[[ endfor ]]</ul>[[ endif ]]".to_string(), None).unwrap();
+    let vars_one: ryuji::Vars = std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String("One".to_string())),
+      ("show".to_string(), ryuji::VarValue::Bool(true)),
+      ("items".to_string(), ryuji::VarValue::Vec(vec![
+        ryuji::VarValue::U32(1),
+        ryuji::VarValue::U32(2),
+      ])),
+    ]);
+    let vars_two: ryuji::Vars = std::collections::HashMap::from([
+      ("title".to_string(), ryuji::VarValue::String("Two".to_string())),
+      ("show".to_string(), ryuji::VarValue::Bool(false)),
+    ]);
+    assert_eq!(template.render(&renderer, &vars_one, None).unwrap(), "<h1>One</h1><ul><li>1</li><li>2</li></ul>");
+    assert_eq!(template.render(&renderer, &vars_two, None).unwrap(), "<h1>Two</h1>");
+    //unbalanced blocks are caught when parsing, not when rendering
+    assert!(matches!(ryuji::Template::new("[[ if:show ]]a".to_string(), None).unwrap_err().without_location(), ryuji::ErrorKind::MissingEndIf));
+    assert!(matches!(ryuji::Template::new("[[ for:items ]]a[[ endif ]]".to_string(), None).unwrap_err().without_location(), ryuji::ErrorKind::MissingEndFor));
+    assert!(ryuji::Template::new("a[[ endfor ]]".to_string(), None).is_err());
+  }
+
+  #[test]
+  fn error_location_test() {
+    let renderer: ryuji::Renderer = ryuji::Renderer::new("templates".to_string(), "components".to_string(), ryuji::FileExtension::new(".html".to_string()).unwrap());
+    let vars: ryuji::Vars = std::collections::HashMap::from([
+      ("items".to_string(), ryuji::VarValue::Vec(vec![ryuji::VarValue::U32(1)])),
+    ]);
+    //render error, inside a for loop
+    let template: ryuji::Template = ryuji::Template::new("<ul>\n  [[ for:items:item ]]\n  <li>[[ item.nam ]]</li>\n  [[ endfor ]]\n</ul>".to_string(), Some("list.html".to_string())).unwrap();
+    let error: ryuji::ErrorKind = template.render(&renderer, &vars, None).unwrap_err();
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::VarNotFound(_)));
+    let location: &ryuji::SourceLocation = error.location().unwrap();
+    assert_eq!((location.template_name.as_deref(), location.line, location.column), (Some("list.html"), 3, 7));
+    assert_eq!(error.to_string(), "Variable 'item.nam' not found\n --> list.html:3:7\n  |\n3 |   <li>[[ item.nam ]]</li>\n  |       ^^^^^^^^^^^^^^");
+    //parse error points at the unclosed statement
+    let error: ryuji::ErrorKind = ryuji::Template::new("a\nb [[ if:items ]]\n[[ for:items ]][[ endfor ]]".to_string(), None).unwrap_err();
+    assert!(matches!(error.without_location(), ryuji::ErrorKind::MissingEndIf));
+    assert_eq!((error.location().unwrap().line, error.location().unwrap().column), (2, 3));
+    //the caret is as long as the tag in chars, even with strings that have non-ascii chars or "]]"
+    let error: ryuji::ErrorKind = renderer.render("é [[ if:x == \"日本語\" ]]a[[ endif ]]".to_string(), &vars, None).unwrap_err();
+    assert_eq!(error.location().unwrap().length, 19);
+    let error: ryuji::ErrorKind = renderer.render("[[ if:a == \"]]\" ]]a[[ endif ]]".to_string(), &vars, None).unwrap_err();
+    assert_eq!(error.location().unwrap().length, 18);
+  }
 }
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::time::SystemTime;
+
+use crate::ryuji::Renderer;
+
+/// Where the `Renderer` gets the contents of templates (and components) from.
+/// `name` is the path of the template with the file extension, eg `post.html`, or `components/card.html` for components.
+pub trait TemplateLoader: Send + Sync {
+  fn load(&self, name: &str) -> io::Result<String>;
+
+  /// Where the template with the name is, for error messages (eg, the file path).
+  fn resolve(&self, name: &str) -> String {
+    name.to_string()
+  }
+
+  /// When the template was last changed, if the loader can tell. Used by `CacheMode::CheckModified` to reload changed templates.
+  fn modified(&self, _name: &str) -> Option<SystemTime> {
+    None
+  }
+}
+
+/// Reads templates from files in a directory.
+pub struct FileSystemLoader {
+  pub templates_dir: String,
+}
+
+impl FileSystemLoader {
+  pub fn new(templates_dir: String) -> Self {
+    Self {
+      templates_dir,
+    }
+  }
+}
+
+impl TemplateLoader for FileSystemLoader {
+  fn load(&self, name: &str) -> io::Result<String> {
+    fs::read_to_string(self.resolve(name))
+  }
+
+  fn resolve(&self, name: &str) -> String {
+    Renderer::concat_path(&self.templates_dir, name)
+  }
+
+  fn modified(&self, name: &str) -> Option<SystemTime> {
+    fs::metadata(self.resolve(name)).and_then(|metadata| metadata.modified()).ok()
+  }
+}
+
+/// Keeps templates in memory, eg for tests, templates stored in a database, or templates embedded in the binary with `include_str!`.
+pub struct MemoryLoader {
+  pub templates: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+  pub fn new(templates: HashMap<String, String>) -> Self {
+    Self {
+      templates,
+    }
+  }
+
+  pub fn insert(&mut self, name: String, template_contents: String) {
+    self.templates.insert(name, template_contents);
+  }
+}
+
+impl TemplateLoader for MemoryLoader {
+  fn load(&self, name: &str) -> io::Result<String> {
+    self.templates.get(name).cloned().ok_or(io::Error::new(io::ErrorKind::NotFound, format!("Template '{}' not found in memory", name)))
+  }
+}
+
+/// Tries each loader in order, until one has the template. Useful for falling back, eg to built in templates if there is no file.
+pub struct ChainLoader {
+  pub loaders: Vec<Box<dyn TemplateLoader>>,
+}
+
+impl ChainLoader {
+  pub fn new(loaders: Vec<Box<dyn TemplateLoader>>) -> Self {
+    Self {
+      loaders,
+    }
+  }
+}
+
+impl TemplateLoader for ChainLoader {
+  fn load(&self, name: &str) -> io::Result<String> {
+    for loader in &self.loaders {
+      match loader.load(name) {
+        //only go to the next loader if this one does not have it, other errors (eg, no permission) should not be hidden
+        Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+        result => return result,
+      }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("Template '{}' not found by any loader", name)))
+  }
+
+  fn modified(&self, name: &str) -> Option<SystemTime> {
+    self.loaders.iter().find_map(|loader| loader.modified(name))
+  }
+}
@@ -1,8 +1,15 @@
-use std::collections::{ HashMap, VecDeque };
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{ HashMap, HashSet, VecDeque };
 use std::fmt;
-use std::fs;
+use std::io;
+use std::sync::{ Arc, Mutex };
+use std::time::SystemTime;
 use std::convert::TryFrom;
 
+use crate::loader::{ TemplateLoader, FileSystemLoader };
+use crate::filters::{ self, Filter, Function };
+
 #[cfg(feature = "hashmap_json")]
 use serde::Serialize;
 
@@ -14,7 +21,45 @@ pub enum ErrorKind {
   BadArgument(String),
   MissingEndFor,
   MissingEndIf,
-  RecursionTooDeep,
+  MissingEndComponent,
+  MissingEndSlot,
+  MissingEndBlock,
+  MissingEndMacro,
+  RecursionTooDeep(usize, Vec<String>), //the max depth, and the chain of templates, components and macros that went over it
+  RecursionCycle(Vec<String>), //the chain of components and macros that loops back around
+  IllegalTemplatePath(String),
+  TemplateNotFound(String, TemplateKind), //the resolved path of the template
+  Io(String, TemplateKind, io::Error), //the resolved path of the template, and what went wrong loading it
+  Output(io::Error), //could not write the rendered output
+  Located(Box<ErrorKind>, SourceLocation),
+}
+
+impl ErrorKind {
+  /// Attach where in a template the error happened. Errors that already have a location (eg, ones from inside a component) keep their original one.
+  pub fn at(self, location: SourceLocation) -> Self {
+    if let ErrorKind::Located(_, _) = self {
+      self
+    } else {
+      ErrorKind::Located(Box::new(self), location)
+    }
+  }
+
+  /// The error without any location information attached.
+  pub fn without_location(&self) -> &ErrorKind {
+    if let ErrorKind::Located(error, _) = self {
+      error.without_location()
+    } else {
+      self
+    }
+  }
+
+  pub fn location(&self) -> Option<&SourceLocation> {
+    if let ErrorKind::Located(_, location) = self {
+      Some(location)
+    } else {
+      None
+    }
+  }
 }
 
 impl fmt::Display for ErrorKind {
@@ -26,11 +71,79 @@ impl fmt::Display for ErrorKind {
       ErrorKind::BadArgument(missing_message) => write!(f, "{}", missing_message),
       ErrorKind::MissingEndFor => write!(f, "`for:` statement missing `[[ endfor ]]`"),
       ErrorKind::MissingEndIf => write!(f, "`if:` statement missing `[[ endif ]]`"),
-      ErrorKind::RecursionTooDeep => write!(f, "`component:` statement recursion too deep (>5)"),
+      ErrorKind::MissingEndComponent => write!(f, "`component:` statement missing `[[ endcomponent ]]`"),
+      ErrorKind::MissingEndSlot => write!(f, "`slot:` statement missing `[[ endslot ]]`"),
+      ErrorKind::MissingEndBlock => write!(f, "`block:` statement missing `[[ endblock ]]`"),
+      ErrorKind::MissingEndMacro => write!(f, "`macro:` statement missing `[[ endmacro ]]`"),
+      ErrorKind::RecursionTooDeep(max_depth, chain) => write!(f, "Components and macros nested too deep (>{}): {}", max_depth, chain.join(" -> ")),
+      ErrorKind::RecursionCycle(chain) => write!(f, "Components and macros include each other forever: {}", chain.join(" -> ")),
+      ErrorKind::IllegalTemplatePath(template_name) => write!(f, "Illegal template path: '{}' (cannot be absolute or contain '..')", template_name),
+      ErrorKind::TemplateNotFound(path, template_kind) => write!(f, "{} '{}' not found", template_kind, path),
+      ErrorKind::Io(path, template_kind, error) => write!(f, "Could not load {} '{}': {}", template_kind, path, error),
+      ErrorKind::Output(error) => write!(f, "Could not write rendered output: {}", error),
+      ErrorKind::Located(error, location) => write!(f, "{}\n{}", error, location),
+    }
+  }
+}
+
+/// What a template is being loaded as, so errors can say.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TemplateKind {
+  Page, //templates rendered directly, and templates they extend or import
+  Component,
+}
+
+impl fmt::Display for TemplateKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      TemplateKind::Page => write!(f, "Template"),
+      TemplateKind::Component => write!(f, "Component"),
+    }
+  }
+}
+
+/// Where in a template something (usually an error) is. Lines and columns start at 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+  pub template_name: Option<String>,
+  pub line: usize,
+  pub column: usize,
+  pub line_contents: String,
+  pub length: usize, //length of the `[[ ... ]]` tag, in chars
+}
+
+impl SourceLocation {
+  /// `index` is the start (byte) index of the `[[ ... ]]` tag in the template, same as `SyntaxMatch::index`. The column is counted in chars, not bytes.
+  pub fn new(template_name: &Option<String>, template_contents: &str, index: usize) -> Self {
+    let before: &str = &template_contents[..index];
+    let line_start: usize = before.rfind('\n').map(|newline_index| newline_index+1).unwrap_or(0);
+    let line_contents: String = template_contents[line_start..].split('\n').next().unwrap().to_string();
+    let column: usize = before[line_start..].chars().count()+1;
+    //tags cannot span lines, but strings in them can have non-ascii chars and "]]", so scan the rest of the line for the tag
+    let length: usize = Renderer::find_syntax_matches(&line_contents[index-line_start..]).first().filter(
+      |syntax_match| syntax_match.index == 0
+    ).map(|syntax_match| syntax_match.content.chars().count()).unwrap_or(1);
+    SourceLocation {
+      template_name: template_name.clone(),
+      line: before.matches('\n').count()+1,
+      column,
+      line_contents,
+      length,
     }
   }
 }
 
+impl fmt::Display for SourceLocation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let line_number: String = self.line.to_string();
+    let padding: String = " ".repeat(line_number.len());
+    writeln!(f, "{}--> {}:{}:{}", padding, self.template_name.as_deref().unwrap_or("(unnamed template)"), self.line, self.column)?;
+    writeln!(f, "{} |", padding)?;
+    writeln!(f, "{} | {}", line_number, self.line_contents)?;
+    write!(f, "{} | {}{}", padding, " ".repeat(self.column-1), "^".repeat(self.length))
+  }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct FileExtension {
   file_extension: String,
@@ -68,8 +181,7 @@ impl From<FileExtension> for String {
 }
 
 
-#[derive(Clone, PartialEq)]
-#[cfg_attr(feature = "hashmap_json", derive(Debug))]
+#[derive(Clone, Debug, PartialEq)]
 pub enum VarValue {
   Bool(bool),
   String(String),
@@ -77,17 +189,59 @@ pub enum VarValue {
   U32(u32),
   Vec(Vec<VarValue>),
   HashMap(HashMap<String, VarValue>),
+  Undefined, //from optional lookups (eg, `post?.subtitle`) that did not find anything, renders as nothing
 }
 
+static UNDEFINED: VarValue = VarValue::Undefined;
+
 impl VarValue {
   pub fn is_truthy(&self) -> bool {
     match self {
       Self::Bool(boolean) => *boolean,
-      Self::String(string) => string == "",
+      Self::String(string) => !string.is_empty(),
       Self::F64(decimal) => *decimal != 0.0,
       Self::U32(integer) => *integer != 0,
-      Self::Vec(vector) => vector.len() > 0,
+      Self::Vec(vector) => !vector.is_empty(),
       Self::HashMap(hashmap) => hashmap.keys().len() > 0,
+      Self::Undefined => false,
+    }
+  }
+
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Self::Bool(_) => "bool",
+      Self::String(_) => "string",
+      Self::F64(_) => "f64",
+      Self::U32(_) => "u32",
+      Self::Vec(_) => "vector",
+      Self::HashMap(_) => "hashmap",
+      Self::Undefined => "undefined",
+    }
+  }
+
+  fn as_f64(&self) -> Option<f64> {
+    match self {
+      Self::F64(decimal) => Some(*decimal),
+      Self::U32(integer) => Some(*integer as f64),
+      _ => None,
+    }
+  }
+
+  /// Like `==`, except `U32`s and `F64`s are compared by their number value, so `U32(1)` equals `F64(1.0)`.
+  pub fn equals(&self, other: &VarValue) -> bool {
+    match (self.as_f64(), other.as_f64()) {
+      (Some(number), Some(other_number)) => number == other_number,
+      _ => self == other,
+    }
+  }
+
+  /// Order two numbers (`U32`s and `F64`s can be mixed, and are compared by their number value) or two strings (alphabetically).
+  /// Anything else cannot be ordered, so `None` is returned.
+  pub fn compare(&self, other: &VarValue) -> Option<Ordering> {
+    match (self, other) {
+      (Self::U32(integer), Self::U32(other_integer)) => Some(integer.cmp(other_integer)),
+      (Self::String(string), Self::String(other_string)) => Some(string.cmp(other_string)),
+      _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
     }
   }
 }
@@ -95,12 +249,13 @@ impl VarValue {
 impl fmt::Display for VarValue {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      VarValue::Bool(boolean) => write!(f, "{}", boolean.to_string()),
+      VarValue::Bool(boolean) => write!(f, "{}", boolean),
       VarValue::String(string) => write!(f, "{}", string),
-      VarValue::F64(decimal) => write!(f, "{}", decimal.to_string()),
-      VarValue::U32(integer) => write!(f, "{}", integer.to_string()),
+      VarValue::F64(decimal) => write!(f, "{}", decimal),
+      VarValue::U32(integer) => write!(f, "{}", integer),
       VarValue::Vec(vector) => write!(f, "{:?}", vector.iter().map(|a| format!("{}", a)).collect::<Vec<String>>()),
       VarValue::HashMap(_hashmap) => write!(f, "Enable the `hashmap_json` crate feature"),
+      VarValue::Undefined => Ok(()),
     }
   }
 }
@@ -115,6 +270,7 @@ impl fmt::Display for VarValue {
       VarValue::U32(integer) => write!(f, "{}", integer.to_string()),
       VarValue::Vec(vector) => write!(f, "{:?}", vector.iter().map(|a| format!("{}", a)).collect::<Vec<String>>()),
       VarValue::HashMap(hashmap) => write!(f, "{}", serde_json::to_string(hashmap)),
+      VarValue::Undefined => Ok(()),
     }
   }
 }
@@ -122,24 +278,1171 @@ impl fmt::Display for VarValue {
 #[derive(Clone, Debug, PartialEq)]
 pub struct SyntaxMatch {
   pub content: String,
-  pub index: usize, //start index in text
+  pub index: usize, //start index in text, in bytes
 }
 
 pub struct ForLoopInfo {
-  index: usize,
   total: usize,
   current: usize,
-  var_value: Vec<VarValue>, //value we are looping over
+  var_value: Vec<VarValue>, //value we are looping over (for hashmaps, the values, in the same order as the keys)
+  keys: Option<Vec<String>>, //only if looping over a hashmap, sorted
+  key_var_name: Option<String>,
   iter_var_name: Option<String>,
   index_var_name: Option<String>,
 }
 
+impl ForLoopInfo {
+  /// The `loop` variable that is automatically available inside for loops, for the last (innermost) loop in `for_loops`.
+  /// It has `index` (starts at 0), `index1` (starts at 1), `first`, `last`, `length`, `even` and `odd` (going by `index1`, so the first item is odd),
+  /// `depth` (1 for a loop not inside of any other loops), and `parent` (the `loop` variable of the loop this one is inside of, if any).
+  pub(crate) fn loop_var(for_loops: &[ForLoopInfo]) -> VarValue {
+    let current_loop: &ForLoopInfo = for_loops.last().expect("no for loop");
+    let mut loop_var: Vars = HashMap::from([
+      ("index".to_string(), VarValue::U32(current_loop.current as u32)),
+      ("index1".to_string(), VarValue::U32(current_loop.current as u32+1)),
+      ("first".to_string(), VarValue::Bool(current_loop.current == 0)),
+      ("last".to_string(), VarValue::Bool(current_loop.current+1 == current_loop.total)),
+      ("length".to_string(), VarValue::U32(current_loop.total as u32)),
+      ("even".to_string(), VarValue::Bool((current_loop.current+1).is_multiple_of(2))),
+      ("odd".to_string(), VarValue::Bool(!(current_loop.current+1).is_multiple_of(2))),
+      ("depth".to_string(), VarValue::U32(for_loops.len() as u32)),
+    ]);
+    if for_loops.len() > 1 {
+      loop_var.insert("parent".to_string(), Self::loop_var(&for_loops[..for_loops.len()-1]));
+    }
+    VarValue::HashMap(loop_var)
+  }
+}
+
 pub type Vars = HashMap<String, VarValue>;
 
+/// The variables visible while rendering: the `Vars` passed in to render with, plus scopes on top of them (eg, the variables of a for loop).
+/// Variables in inner scopes shadow ones with the same name in outer scopes, and disappear once their scope is popped, so the original `Vars` are never changed.
+pub struct Scope<'a> {
+  vars: &'a Vars,
+  scopes: Vec<Vars>, //innermost last
+}
+
+impl<'a> Scope<'a> {
+  pub fn new(vars: &'a Vars) -> Self {
+    Self {
+      vars,
+      scopes: Vec::new(),
+    }
+  }
+
+  pub fn push(&mut self) {
+    self.scopes.push(HashMap::new());
+  }
+
+  pub fn pop(&mut self) {
+    self.scopes.pop();
+  }
+
+  /// Set a variable in the innermost scope. Panics if no scope was pushed, since the original `Vars` cannot be changed.
+  pub(crate) fn set(&mut self, var_name: String, var_value: VarValue) {
+    self.scopes.last_mut().expect("no scope to set variable in").insert(var_name, var_value);
+  }
+
+  pub fn get(&self, var_name: &str) -> Option<&VarValue> {
+    self.scopes.iter().rev().find_map(|scope| scope.get(var_name)).or_else(|| self.vars.get(var_name))
+  }
+
+  /// Same as `Renderer::get_var`, but looks through all the scopes.
+  pub fn get_var(&self, var_name: String) -> Result<&VarValue, ErrorKind> {
+    Renderer::get_var_with(var_name, |part_uno| self.get(part_uno))
+  }
+}
+
+/// A node of a parsed template. Text between `[[ ... ]]` tags becomes `Text`, each tag becomes one of the other variants, and block statements (`if:`, `for:`) hold the nodes up until their end tag.
+/// `index` is the start (byte) index of the tag in the template text, same as `SyntaxMatch::index`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+  Text(String),
+  Var {
+    index: usize,
+    expr: Expr, //the variable, and any filters it goes through
+    html: bool, //if true, the variable is not sanitized
+  },
+  If {
+    index: usize,
+    branches: Vec<IfBranch>, //the `if:` and then any `elif:`s, only the first one whose condition passes is rendered
+    else_body: Option<Vec<Node>>, //rendered if none of the conditions pass
+  },
+  For {
+    index: usize,
+    source: Expr, //usually just the variable to loop over, but can have filters
+    loop_var_names: Vec<String>, //for vectors: iter, index and max variable names, for hashmaps: key, value, index and max variable names. all optional
+    body: Vec<Node>,
+    empty_body: Option<Vec<Node>>, //rendered instead if there is nothing to loop over
+  },
+  Component {
+    index: usize,
+    file_name: String,
+    props: Vec<(String, Expr)>, //variables passed to the component, eg `title=post.title`
+    only: bool, //if true, the component can only see its props, not the variables of where it is used
+    body: Option<Vec<Node>>, //for the block form (`[[ component:name ]] ... [[ endcomponent ]]`), the content passed in as the default slot
+    slots: Vec<(String, Vec<Node>)>, //named slots, from `[[ slot:name ]] ... [[ endslot ]]` inside the block form
+  },
+  Block {
+    index: usize,
+    name: String,
+    body: Vec<Node>, //rendered unless a template extending this one has a block with the same name
+  },
+  Parent {
+    index: usize, //`[[ parent ]]` inside of a block, renders the block it overrides
+  },
+  Call {
+    index: usize,
+    name: String, //name of the macro
+    args: Vec<Expr>,
+  },
+}
+
+impl Node {
+  pub fn index(&self) -> Option<usize> {
+    match self {
+      Node::Text(_) => None,
+      Node::Var { index, .. } | Node::If { index, .. } | Node::For { index, .. } | Node::Component { index, .. } | Node::Block { index, .. } | Node::Parent { index } | Node::Call { index, .. } => Some(*index),
+    }
+  }
+
+  //whether the variable (or any part of it, eg `slots.header` for `slots`) is used in the node or the nodes inside of it
+  fn uses_var(&self, var_name: &str) -> bool {
+    let any_uses_var = |nodes: &[Node]| nodes.iter().any(|node| node.uses_var(var_name));
+    match self {
+      Node::Text(_) | Node::Parent { .. } => false,
+      Node::Var { expr, .. } => expr.uses_var(var_name),
+      Node::If { branches, else_body, .. } => branches.iter().any(|branch| branch.condition.uses_var(var_name) || any_uses_var(&branch.body)) || else_body.as_deref().is_some_and(any_uses_var),
+      Node::For { source, body, empty_body, .. } => source.uses_var(var_name) || any_uses_var(body) || empty_body.as_deref().is_some_and(any_uses_var),
+      Node::Component { props, body, slots, .. } => props.iter().any(|(_, prop_value)| prop_value.uses_var(var_name)) || body.as_deref().is_some_and(any_uses_var) || slots.iter().any(|(_, slot_body)| any_uses_var(slot_body)),
+      Node::Block { body, .. } => any_uses_var(body),
+      Node::Call { args, .. } => args.iter().any(|arg| arg.uses_var(var_name)),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+}
+
+impl fmt::Display for CompareOp {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CompareOp::Equal => write!(f, "=="),
+      CompareOp::NotEqual => write!(f, "!="),
+      CompareOp::Less => write!(f, "<"),
+      CompareOp::LessOrEqual => write!(f, "<="),
+      CompareOp::Greater => write!(f, ">"),
+      CompareOp::GreaterOrEqual => write!(f, ">="),
+    }
+  }
+}
+
+/// An expression, like the condition of an `if:` or `elif:` statement.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+  Var(String),
+  Literal(VarValue),
+  Compare(Box<Expr>, CompareOp, Box<Expr>),
+  And(Box<Expr>, Box<Expr>),
+  Or(Box<Expr>, Box<Expr>),
+  Not(Box<Expr>),
+  Filter(Box<Expr>, String, Vec<Expr>), //value, filter name, filter args
+  Function(String, Vec<Expr>), //function name, args
+  Default(Box<Expr>, Box<Expr>), //`<value> ?? <default>`, the default is used if the value is missing or undefined
+  Defined(Box<Expr>), //`<value> is defined` or `<value> exists`
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Name(String), //variable name, or keyword like `true` or `and`
+  Literal(VarValue),
+  CompareOp(CompareOp),
+  Not, //"!", same as `not`
+  OpenParen,
+  CloseParen,
+  Pipe, //"|", before a filter name
+  Colon, //":", before a filter arg
+  Comma, //",", between function args
+  Coalesce, //"??"
+}
+
+impl fmt::Display for Token {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Token::Name(name) => write!(f, "{}", name),
+      Token::Literal(VarValue::String(string)) => write!(f, "\"{}\"", string),
+      Token::Literal(literal) => write!(f, "{}", literal),
+      Token::CompareOp(compare_op) => write!(f, "{}", compare_op),
+      Token::Not => write!(f, "!"),
+      Token::OpenParen => write!(f, "("),
+      Token::CloseParen => write!(f, ")"),
+      Token::Pipe => write!(f, "|"),
+      Token::Colon => write!(f, ":"),
+      Token::Comma => write!(f, ","),
+      Token::Coalesce => write!(f, "??"),
+    }
+  }
+}
+
+impl Expr {
+  /// Parse an expression, eg `post.views > 100`, `status == "draft"`, `is_admin` or `logged_in and (is_admin or not post.locked)`.
+  /// Values can be variables, numbers, strings (in double or single quotes) or `true`/`false`.
+  /// `not` (or `!`) goes before `and`, which goes before `or`. Parentheses can be used to group.
+  /// Values can be passed through filters, eg `post.title|upper|truncate:60`. Filters go before everything else, so `not tags|length > 3` is `not ((tags|length) > 3)`.
+  /// Functions registered with `Renderer::register_function` are called like `route("post", post.slug)`.
+  /// Missing variables can have a default, eg `post.subtitle ?? "Untitled"`, or be checked with `post.subtitle is defined` (or `post.subtitle exists`).
+  /// `a?.b` is an optional lookup, undefined instead of an error if `a` has no `b`. `??` goes after filters and before comparisons.
+  pub fn parse(expression: &str) -> Result<Self, ErrorKind> {
+    let mut expr_parser: ExprParser = ExprParser {
+      expression,
+      tokens: Self::tokenize(expression)?,
+      position: 0,
+    };
+    let expr: Expr = expr_parser.parse_or()?;
+    if expr_parser.position < expr_parser.tokens.len() {
+      return Err(expr_parser.unexpected());
+    }
+    Ok(expr)
+  }
+
+  fn uses_var(&self, var_name: &str) -> bool {
+    match self {
+      Expr::Var(name) => name.split(['.', '?']).next() == Some(var_name),
+      Expr::Literal(_) => false,
+      Expr::Compare(left, _, right) | Expr::And(left, right) | Expr::Or(left, right) | Expr::Default(left, right) => left.uses_var(var_name) || right.uses_var(var_name),
+      Expr::Not(expr) | Expr::Defined(expr) => expr.uses_var(var_name),
+      Expr::Filter(expr, _, args) => expr.uses_var(var_name) || args.iter().any(|arg| arg.uses_var(var_name)),
+      Expr::Function(_, args) => args.iter().any(|arg| arg.uses_var(var_name)),
+    }
+  }
+
+  //tokens, and the char index they start at
+  fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, ErrorKind> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut index: usize = 0;
+    while index < chars.len() {
+      let current_char: char = chars[index];
+      let next_char: Option<char> = chars.get(index+1).copied();
+      if current_char == ' ' {
+        index += 1;
+      } else if current_char == '(' || current_char == ')' {
+        tokens.push((if current_char == '(' { Token::OpenParen } else { Token::CloseParen }, index));
+        index += 1;
+      } else if current_char == '?' && next_char == Some('?') {
+        tokens.push((Token::Coalesce, index));
+        index += 2;
+      } else if current_char == '|' || current_char == ':' || current_char == ',' {
+        tokens.push((match current_char { '|' => Token::Pipe, ':' => Token::Colon, _ => Token::Comma }, index));
+        index += 1;
+      } else if current_char == '!' && next_char != Some('=') {
+        tokens.push((Token::Not, index));
+        index += 1;
+      } else if current_char == '"' || current_char == '\'' {
+        //string, which ends at the next same quote
+        let length: usize = chars[index+1..].iter().position(|c| *c == current_char).ok_or(
+          ErrorKind::BadArgument(format!("String starting at char {} missing closing quote in `{}`", index+1, expression))
+        )?;
+        tokens.push((Token::Literal(VarValue::String(chars[index+1..index+1+length].iter().collect())), index));
+        index += length+2;
+      } else if current_char.is_ascii_digit() || (current_char == '-' && next_char.is_some_and(|c| c.is_ascii_digit())) {
+        let length: usize = chars[index+1..].iter().take_while(|c| c.is_ascii_digit() || **c == '.').count()+1;
+        let number: String = chars[index..index+length].iter().collect();
+        //whole numbers that fit are u32s, anything else is a f64
+        let literal: VarValue = if let Ok(integer) = number.parse::<u32>() {
+          VarValue::U32(integer)
+        } else if let Ok(decimal) = number.parse::<f64>() {
+          VarValue::F64(decimal)
+        } else {
+          return Err(ErrorKind::BadArgument(format!("Invalid number `{}` at char {} of `{}`", number, index+1, expression)));
+        };
+        tokens.push((Token::Literal(literal), index));
+        index += length;
+      } else if current_char == '=' || current_char == '!' || current_char == '<' || current_char == '>' {
+        let (compare_op, length): (CompareOp, usize) = match (current_char, next_char) {
+          ('=', Some('=')) => (CompareOp::Equal, 2),
+          ('!', Some('=')) => (CompareOp::NotEqual, 2),
+          ('<', Some('=')) => (CompareOp::LessOrEqual, 2),
+          ('>', Some('=')) => (CompareOp::GreaterOrEqual, 2),
+          ('<', _) => (CompareOp::Less, 1),
+          ('>', _) => (CompareOp::Greater, 1),
+          _ => return Err(ErrorKind::BadArgument(format!("Unexpected `{}` at char {} of `{}`", current_char, index+1, expression))),
+        };
+        tokens.push((Token::CompareOp(compare_op), index));
+        index += length;
+      } else if current_char.is_ascii_alphanumeric() || current_char == '_' {
+        //"?" is only part of a name in "?.", eg `post?.subtitle`
+        let length: usize = (index..chars.len()).take_while(
+          |char_index| chars[*char_index].is_ascii_alphanumeric() || ['_', '.', '/', '-'].contains(&chars[*char_index]) || (chars[*char_index] == '?' && chars.get(char_index+1) == Some(&'.'))
+        ).count();
+        tokens.push((Token::Name(chars[index..index+length].iter().collect()), index));
+        index += length;
+      } else {
+        return Err(ErrorKind::BadArgument(format!("Unexpected `{}` at char {} of `{}`", current_char, index+1, expression)));
+      }
+    }
+    Ok(tokens)
+  }
+}
+
+struct ExprParser<'a> {
+  expression: &'a str,
+  tokens: Vec<(Token, usize)>,
+  position: usize, //index of the next token
+}
+
+impl ExprParser<'_> {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position).map(|(token, _)| token)
+  }
+
+  fn peek_keyword(&self, keyword: &str) -> bool {
+    matches!(self.peek(), Some(Token::Name(name)) if name == keyword)
+  }
+
+  //error for the next token (or the end, if there are no more tokens)
+  fn unexpected(&self) -> ErrorKind {
+    match self.tokens.get(self.position) {
+      Some((token, index)) => ErrorKind::BadArgument(format!("Unexpected `{}` at char {} of `{}`", token, index+1, self.expression)),
+      None => ErrorKind::BadArgument(format!("Missing value at end of `{}`", self.expression)),
+    }
+  }
+
+  //<and> or <and> or ...
+  fn parse_or(&mut self) -> Result<Expr, ErrorKind> {
+    let mut left: Expr = self.parse_and()?;
+    while self.peek_keyword("or") {
+      self.position += 1;
+      left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+    }
+    Ok(left)
+  }
+
+  //<not> and <not> and ...
+  fn parse_and(&mut self) -> Result<Expr, ErrorKind> {
+    let mut left: Expr = self.parse_not()?;
+    while self.peek_keyword("and") {
+      self.position += 1;
+      left = Expr::And(Box::new(left), Box::new(self.parse_not()?));
+    }
+    Ok(left)
+  }
+
+  //not <not> or <comparison>
+  fn parse_not(&mut self) -> Result<Expr, ErrorKind> {
+    if self.peek_keyword("not") || self.peek() == Some(&Token::Not) {
+      self.position += 1;
+      return Ok(Expr::Not(Box::new(self.parse_not()?)));
+    }
+    self.parse_comparison()
+  }
+
+  //<default>, <default> <compare op> <default>, <default> is [not] defined, or <default> exists
+  fn parse_comparison(&mut self) -> Result<Expr, ErrorKind> {
+    let left: Expr = self.parse_default()?;
+    if self.peek_keyword("is") {
+      self.position += 1;
+      let negated: bool = self.peek_keyword("not");
+      if negated {
+        self.position += 1;
+      }
+      if !self.peek_keyword("defined") {
+        return Err(self.unexpected());
+      }
+      self.position += 1;
+      let defined: Expr = Expr::Defined(Box::new(left));
+      return Ok(if negated { Expr::Not(Box::new(defined)) } else { defined });
+    } else if self.peek_keyword("exists") {
+      self.position += 1;
+      return Ok(Expr::Defined(Box::new(left)));
+    }
+    if let Some(Token::CompareOp(compare_op)) = self.peek() {
+      let compare_op: CompareOp = *compare_op;
+      self.position += 1;
+      let right: Expr = self.parse_default()?;
+      return Ok(Expr::Compare(Box::new(left), compare_op, Box::new(right)));
+    }
+    Ok(left)
+  }
+
+  //<filtered> ?? <filtered> ?? ...
+  fn parse_default(&mut self) -> Result<Expr, ErrorKind> {
+    let left: Expr = self.parse_filtered()?;
+    if self.peek() == Some(&Token::Coalesce) {
+      self.position += 1;
+      return Ok(Expr::Default(Box::new(left), Box::new(self.parse_default()?)));
+    }
+    Ok(left)
+  }
+
+  //<value>|<filter name>:<value>:<value>|...
+  fn parse_filtered(&mut self) -> Result<Expr, ErrorKind> {
+    let mut expr: Expr = self.parse_value()?;
+    while self.peek() == Some(&Token::Pipe) {
+      self.position += 1;
+      let filter_name: String = match self.peek() {
+        Some(Token::Name(name)) if name != "and" && name != "or" && name != "not" => name.clone(),
+        _ => return Err(self.unexpected()),
+      };
+      Renderer::check_var_name_legality(&filter_name, false)?;
+      self.position += 1;
+      let mut args: Vec<Expr> = Vec::new();
+      while self.peek() == Some(&Token::Colon) {
+        self.position += 1;
+        args.push(self.parse_value()?);
+      }
+      expr = Expr::Filter(Box::new(expr), filter_name, args);
+    }
+    Ok(expr)
+  }
+
+  //<function name>(<or>, <or>, ...), the name has already been parsed
+  fn parse_function(&mut self, function_name: String) -> Result<Expr, ErrorKind> {
+    let open_index: usize = self.tokens[self.position].1;
+    self.position += 1;
+    let mut args: Vec<Expr> = Vec::new();
+    loop {
+      match self.peek() {
+        Some(Token::CloseParen) => break,
+        None => return Err(ErrorKind::BadArgument(format!("Missing `)` for the `(` at char {} of `{}`", open_index+1, self.expression))),
+        _ => {},
+      }
+      args.push(self.parse_or()?);
+      match self.peek() {
+        Some(Token::Comma) => self.position += 1,
+        Some(Token::CloseParen) => break,
+        _ => return Err(self.unexpected()),
+      }
+    }
+    self.position += 1;
+    Ok(Expr::Function(function_name, args))
+  }
+
+  //a variable, literal, function call, or an expression in parentheses
+  fn parse_value(&mut self) -> Result<Expr, ErrorKind> {
+    let (token, index): (Token, usize) = self.tokens.get(self.position).cloned().ok_or_else(|| self.unexpected())?;
+    match token {
+      Token::OpenParen => {
+        self.position += 1;
+        let expr: Expr = self.parse_or()?;
+        if self.peek() != Some(&Token::CloseParen) {
+          if self.position < self.tokens.len() {
+            return Err(self.unexpected());
+          }
+          return Err(ErrorKind::BadArgument(format!("Missing `)` for the `(` at char {} of `{}`", index+1, self.expression)));
+        }
+        self.position += 1;
+        Ok(expr)
+      },
+      Token::Name(name) if name == "and" || name == "or" || name == "not" => Err(self.unexpected()),
+      Token::Name(name) => {
+        self.position += 1;
+        if name == "true" || name == "false" {
+          return Ok(Expr::Literal(VarValue::Bool(name == "true")));
+        }
+        if self.peek() == Some(&Token::OpenParen) {
+          Renderer::check_var_name_legality(&name, false)?;
+          return self.parse_function(name);
+        }
+        Renderer::check_var_name_legality(&name.replace("?.", "."), true)?;
+        Ok(Expr::Var(name))
+      },
+      Token::Literal(literal) => {
+        self.position += 1;
+        Ok(Expr::Literal(literal))
+      },
+      _ => Err(self.unexpected()),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IfBranch {
+  pub index: usize, //start index of the `if:` or `elif:` tag
+  pub condition: Expr,
+  pub body: Vec<Node>,
+}
+
+/// A snippet defined with `[[ macro:name:arg_name:... ]] ... [[ endmacro ]]`, and rendered with `[[ call:name:arg:... ]]`.
+/// Macros only see their args and the `Vars` passed in to render with, not the variables of where they are called (eg, for loop ones).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macro {
+  pub index: usize,
+  pub name: String,
+  pub arg_names: Vec<String>,
+  pub body: Vec<Node>,
+}
+
+/// A template that has been parsed once and can then be rendered as many times as needed, with different `Vars`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+  name: Option<String>,
+  template_contents: String,
+  nodes: Vec<Node>,
+  extends: Option<(String, usize)>, //name of the template from `[[ extends:name ]]`, and the index of the tag
+  macros: HashMap<String, Macro>,
+  imports: Vec<(String, usize)>, //names of the templates to use the macros of, from `[[ import:name ]]`, and the index of the tag
+}
+
+impl Template {
+  /// `name` is only used to say where errors happened, usually it is the file name of the template.
+  pub fn new(template_contents: String, name: Option<String>) -> Result<Self, ErrorKind> {
+    let syntax_matches: Vec<SyntaxMatch> = Renderer::find_syntax_matches(&template_contents);
+    let mut parser: Parser = Parser {
+      template_name: &name,
+      template_contents: &template_contents,
+      block_components: Parser::find_block_components(&syntax_matches),
+      syntax_matches,
+      index: 0,
+      position: 0,
+      open_statements: Vec::new(),
+      extends: None,
+      block_names: HashSet::new(),
+      macros: HashMap::new(),
+      imports: Vec::new(),
+    };
+    let (nodes, end_tag) = parser.parse_nodes()?;
+    if let Some(end_tag) = end_tag {
+      return Err(ErrorKind::BadArgument(format!("`[[ {} ]]` does not have a matching statement to end", end_tag.name)).at(SourceLocation::new(&name, &template_contents, end_tag.index)));
+    }
+    let extends: Option<(String, usize)> = parser.extends;
+    let macros: HashMap<String, Macro> = parser.macros;
+    let imports: Vec<(String, usize)> = parser.imports;
+    Ok(Template {
+      name,
+      template_contents,
+      nodes,
+      extends,
+      macros,
+      imports,
+    })
+  }
+
+  pub fn name(&self) -> Option<&String> {
+    self.name.as_ref()
+  }
+
+  pub fn location(&self, index: usize) -> SourceLocation {
+    SourceLocation::new(&self.name, &self.template_contents, index)
+  }
+
+  pub fn nodes(&self) -> &Vec<Node> {
+    &self.nodes
+  }
+
+  /// The name of the template this one extends (`[[ extends:name ]]`), if any.
+  pub fn extends(&self) -> Option<&String> {
+    self.extends.as_ref().map(|(extends, _)| extends)
+  }
+
+  /// The macros defined in the template (not including imported ones).
+  pub fn macros(&self) -> &HashMap<String, Macro> {
+    &self.macros
+  }
+
+  //whether the `slot` or `slots` variables are used anywhere, including in macros
+  fn uses_slots(&self) -> bool {
+    self.nodes.iter().chain(self.macros.values().flat_map(|macro_def| macro_def.body.iter())).any(|node| node.uses_var("slot") || node.uses_var("slots"))
+  }
+
+  //finds the body of the `block:` with the name, even if it is nested inside of other statements
+  fn find_block<'a>(nodes: &'a [Node], name: &str) -> Option<&'a Vec<Node>> {
+    nodes.iter().find_map(|node| match node {
+      Node::Block { name: block_name, body, .. } if block_name == name => Some(body),
+      Node::Block { body, .. } => Self::find_block(body, name),
+      Node::If { branches, else_body, .. } => branches.iter().find_map(|branch| Self::find_block(&branch.body, name)).or_else(
+        || else_body.as_deref().and_then(|else_body| Self::find_block(else_body, name))
+      ),
+      Node::For { body, empty_body, .. } => Self::find_block(body, name).or_else(
+        || empty_body.as_deref().and_then(|empty_body| Self::find_block(empty_body, name))
+      ),
+      Node::Component { body, slots, .. } => body.as_deref().and_then(|body| Self::find_block(body, name)).or_else(
+        || slots.iter().find_map(|(_, slot_body)| Self::find_block(slot_body, name))
+      ),
+      _ => None,
+    })
+  }
+
+  /// If the template extends another template, the base template is rendered instead, with any blocks this template has overriding the base template's.
+  pub fn render(&self, renderer: &Renderer, vars: &Vars, recursion_layer: Option<usize>) -> Result<String, ErrorKind> {
+    let mut rendered: String = String::new();
+    self.render_to_fmt(&mut rendered, renderer, vars, recursion_layer)?;
+    Ok(rendered)
+  }
+
+  /// Same as `render`, but the output is written to `writer` as it is rendered, instead of being returned as a `String`.
+  /// Lots of small writes are made, so wrap `writer` in a `BufWriter` if writes are expensive (eg, files and sockets).
+  pub fn render_to<W: io::Write>(&self, writer: &mut W, renderer: &Renderer, vars: &Vars, recursion_layer: Option<usize>) -> Result<(), ErrorKind> {
+    let mut io_writer: IoWriter<W> = IoWriter {
+      writer,
+      error: None,
+    };
+    self.render_to_fmt(&mut io_writer, renderer, vars, recursion_layer).map_err(
+      |error| match io_writer.error.take() {
+        Some(io_error) => ErrorKind::Output(io_error),
+        None => error,
+      }
+    )
+  }
+
+  /// Same as `render_to`, but for `fmt::Write`, eg a `String` or `fmt::Formatter`.
+  pub fn render_to_fmt<W: fmt::Write>(&self, writer: &mut W, renderer: &Renderer, vars: &Vars, recursion_layer: Option<usize>) -> Result<(), ErrorKind> {
+    let parents: Vec<Arc<Template>> = renderer.load_parents(self)?;
+    let inheritance: Vec<&Template> = std::iter::once(self).chain(parents.iter().map(|parent| parent.as_ref())).collect();
+    let mut state: RenderState = RenderState {
+      scope: Scope::new(vars),
+      for_loops: Vec::new(),
+      recursion_layer: recursion_layer.unwrap_or(0),
+      imports: renderer.load_imports(&inheritance)?,
+      inheritance,
+      blocks: Vec::new(),
+      includes: vec![(self.name.clone().unwrap_or("(unnamed template)".to_string()), HashMap::new(), None)],
+    };
+    renderer.render_inherited(&mut state, &mut Output::new(writer))
+  }
+}
+
+//lets `io::Write`s be written to as a `fmt::Write`, keeping the actual error, since `fmt::Error` has no info
+struct IoWriter<'a, W: io::Write> {
+  writer: &'a mut W,
+  error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<'_, W> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.writer.write_all(s.as_bytes()).map_err(|error| {
+      self.error = Some(error);
+      fmt::Error
+    })
+  }
+}
+
+//where rendered output is written to, keeping track of the indentation of the current line so multi-line variables can be indented to match
+struct Output<'a> {
+  writer: &'a mut dyn fmt::Write,
+  indentation: usize, //spaces at the start of the current line
+  line_started: bool, //whether anything other than spaces has been written on the current line
+  newline_written: bool,
+}
+
+impl<'a> Output<'a> {
+  fn new(writer: &'a mut dyn fmt::Write) -> Self {
+    Self {
+      writer,
+      indentation: 0,
+      line_started: false,
+      newline_written: false,
+    }
+  }
+
+  fn write(&mut self, text: &str) -> Result<(), ErrorKind> {
+    let current_line: &str = match text.rfind('\n') {
+      Some(newline_index) => {
+        self.indentation = 0;
+        self.line_started = false;
+        self.newline_written = true;
+        &text[newline_index+1..]
+      },
+      None => text,
+    };
+    if !self.line_started {
+      let spaces: usize = current_line.bytes().take_while(|byte| *byte == b' ').count();
+      self.indentation += spaces;
+      self.line_started = spaces < current_line.len();
+    }
+    self.writer.write_str(text).map_err(|_| ErrorKind::Output(io::Error::other("formatter error")))
+  }
+
+  //components are rendered like they are on their own, so indentation inside of them starts from nothing
+  fn start_include(&mut self) -> (usize, bool, bool) {
+    let saved: (usize, bool, bool) = (self.indentation, self.line_started, self.newline_written);
+    self.indentation = 0;
+    self.line_started = false;
+    self.newline_written = false;
+    saved
+  }
+
+  fn end_include(&mut self, (indentation, line_started, newline_written): (usize, bool, bool)) {
+    //if the component did not write a newline, it is still on the same line as before it
+    if !self.newline_written {
+      if line_started {
+        self.indentation = indentation;
+        self.line_started = true;
+      } else {
+        self.indentation += indentation;
+      }
+    }
+    self.newline_written |= newline_written;
+  }
+}
+
+//tag that ends a block statement (eg, `[[ endif ]]`), or one part of it (eg, `[[ else ]]`)
+struct EndTag {
+  name: String,
+  args: Vec<String>,
+  index: usize,
+}
+
+struct Parser<'a> {
+  template_name: &'a Option<String>,
+  template_contents: &'a String,
+  syntax_matches: Vec<SyntaxMatch>,
+  index: usize, //index of the next syntax match to parse
+  position: usize, //where in the text the last syntax match ended
+  open_statements: Vec<String>, //block statements (eg, "for") that we are currently inside of, innermost last
+  block_components: HashSet<usize>, //indexes of `component:` tags that have a matching `[[ endcomponent ]]`
+  extends: Option<(String, usize)>,
+  block_names: HashSet<String>, //names of the `block:`s so far, since they must be unique
+  macros: HashMap<String, Macro>,
+  imports: Vec<(String, usize)>,
+}
+
+impl Parser<'_> {
+  //the first arg of a tag, or "" for ones that are not statements (eg, `[[ slot ]]` is a variable, `[[ slot:name ]]` is a statement)
+  fn statement_name(syntax_match: &SyntaxMatch) -> &str {
+    let exp_parts: Vec<&str> = Self::split_args(&syntax_match.content[3..syntax_match.content.len()-3]);
+    match exp_parts[0] {
+      "if" | "elif" | "for" | "slot" | "block" | "macro" if exp_parts.len() < 2 => "",
+      statement => statement,
+    }
+  }
+
+  //`[[ component:name ]]` on its own is a component with no content, so to tell if it starts a block, look for an `[[ endcomponent ]]` to pair it with
+  //an `[[ endcomponent ]]` goes with the innermost unpaired `component:` tag in the same `if:`/`for:`/`slot:` block (or branch of one), just like html tags
+  fn find_block_components(syntax_matches: &[SyntaxMatch]) -> HashSet<usize> {
+    let mut block_components: HashSet<usize> = HashSet::new();
+    let mut open: Vec<Option<usize>> = Vec::new(); //`component:` tag indexes, and `None` for other block statements
+    for syntax_match in syntax_matches {
+      match Self::statement_name(syntax_match) {
+        "component" => open.push(Some(syntax_match.index)),
+        "if" | "for" | "slot" | "block" | "macro" => open.push(None),
+        "elif" | "else" | "forelse" => {
+          //components that were not closed inside the branch are not block components
+          while let Some(Some(_)) = open.last() {
+            open.pop();
+          }
+        },
+        "endif" | "endfor" | "endslot" | "endblock" | "endmacro" => {
+          while let Some(Some(_)) = open.last() {
+            open.pop();
+          }
+          open.pop();
+        },
+        "endcomponent" => {
+          if let Some(Some(_)) = open.last() {
+            block_components.insert(open.pop().unwrap().unwrap());
+          }
+        },
+        _ => {},
+      }
+    }
+    block_components
+  }
+
+  //parses until the end of the template, or until an end tag (`endif`, `elif:`, `else`, `endfor`, `forelse`, `endcomponent`, `slot:`, `endslot`, `endblock`, `endmacro`) is hit, which is returned so the caller can check it is the right one
+  fn parse_nodes(&mut self) -> Result<(Vec<Node>, Option<EndTag>), ErrorKind> {
+    let mut nodes: Vec<Node> = Vec::new();
+    while self.index < self.syntax_matches.len() {
+      let syntax_match: SyntaxMatch = self.syntax_matches[self.index].clone();
+      //add the html that comes before this, starting from the end of the previous template syntax match thing
+      if syntax_match.index > self.position {
+        nodes.push(Node::Text(self.template_contents[self.position..syntax_match.index].to_string()));
+      }
+      self.index += 1;
+      self.position = syntax_match.index+syntax_match.content.len();
+      let end_tag: Option<EndTag> = self.parse_tag(&syntax_match, &mut nodes).map_err(
+        |error| error.at(SourceLocation::new(self.template_name, self.template_contents, syntax_match.index))
+      )?;
+      if end_tag.is_some() {
+        return Ok((nodes, end_tag));
+      }
+    }
+    //add all the way till end of template
+    if self.position < self.template_contents.len() {
+      nodes.push(Node::Text(self.template_contents[self.position..].to_string()));
+      self.position = self.template_contents.len();
+    }
+    Ok((nodes, None))
+  }
+
+  //splits the inside of a `[[ ... ]]` on ":"s, but not ones inside of strings or parentheses (eg, `for:(posts|truncate:10):post`)
+  fn split_args(content: &str) -> Vec<&str> {
+    Self::split_outside(content, ':')
+  }
+
+  fn split_outside(content: &str, separator: char) -> Vec<&str> {
+    let mut args: Vec<&str> = Vec::new();
+    let mut in_quote: Option<char> = None;
+    let mut paren_depth: usize = 0;
+    let mut arg_start: usize = 0;
+    for (index, current_char) in content.char_indices() {
+      if let Some(quote) = in_quote {
+        if current_char == quote {
+          in_quote = None;
+        }
+      } else if current_char == '"' || current_char == '\'' {
+        in_quote = Some(current_char);
+      } else if current_char == '(' {
+        paren_depth += 1;
+      } else if current_char == ')' {
+        paren_depth = paren_depth.saturating_sub(1);
+      } else if current_char == separator && paren_depth == 0 {
+        args.push(&content[arg_start..index]);
+        arg_start = index+1;
+      }
+    }
+    args.push(&content[arg_start..]);
+    args
+  }
+
+  //`if:` and `elif:` statements share the same condition syntax
+  fn parse_condition(statement: &str, args: &[&str]) -> Result<Expr, ErrorKind> {
+    if args.is_empty() {
+      Err(ErrorKind::BadArgument(format!("`{}:` statement missing condition (second arg)", statement)))
+    } else if args.len() == 1 || Self::split_outside(&args.join(":"), '|').len() > 1 {
+      //eg, `if:var_name`, `if:post.views > 100` or `if:post.title|truncate:5 == "hello"` (filter args also use ":")
+      Expr::parse(&args.join(":"))
+    } else if args.len() == 2 {
+      //the old way to compare two variables, eg `if:var_name:compare_var_name` or `if:var_name:!compare_var_name` (not equal)
+      let (var_name2, compare_op): (&str, CompareOp) = match args[1].strip_prefix('!') {
+        Some(stripped) => (stripped, CompareOp::NotEqual),
+        None => (args[1], CompareOp::Equal),
+      };
+      Renderer::check_var_name_legality(args[0], true)?;
+      Renderer::check_var_name_legality(var_name2, true)?;
+      Ok(Expr::Compare(Box::new(Expr::Var(args[0].to_string())), compare_op, Box::new(Expr::Var(var_name2.to_string()))))
+    } else {
+      Err(ErrorKind::BadArgument(format!("`{}:` statement cannot have more than 3 args", statement)))
+    }
+  }
+
+  //adds the node for the tag, or returns the tag if it is an end tag
+  fn parse_tag(&mut self, syntax_match: &SyntaxMatch, nodes: &mut Vec<Node>) -> Result<Option<EndTag>, ErrorKind> {
+    let index: usize = syntax_match.index;
+    let exp_parts: Vec<&str> = Self::split_args(&syntax_match.content[3..syntax_match.content.len()-3]);
+    if exp_parts[0] == "component" {
+      if exp_parts.len() < 2 {
+        return Err(ErrorKind::BadArgument("`component:` statement missing component name (second arg)".to_string()));
+      }
+      //rest of the args are props (eg, `title=post.title`), or `only`
+      let mut props: Vec<(String, Expr)> = Vec::new();
+      let mut only: bool = false;
+      for arg in &exp_parts[2..] {
+        if *arg == "only" {
+          only = true;
+          continue;
+        }
+        let Some((prop_name, prop_value)) = arg.split_once('=') else {
+          return Err(ErrorKind::BadArgument(format!("`component:` statement arg `{}` should be a prop (eg, `title=post.title`) or `only`", arg)));
+        };
+        Renderer::check_var_name_legality(prop_name, false)?;
+        if props.iter().any(|(existing_name, _)| existing_name == prop_name) {
+          return Err(ErrorKind::BadArgument(format!("`component:` statement has prop `{}` more than once", prop_name)));
+        }
+        props.push((prop_name.to_string(), Expr::parse(prop_value)?));
+      }
+      let mut body: Option<Vec<Node>> = None;
+      let mut slots: Vec<(String, Vec<Node>)> = Vec::new();
+      if self.block_components.contains(&index) {
+        //everything not in a named slot goes in the default slot
+        let mut default_slot: Vec<Node> = Vec::new();
+        self.open_statements.push("component".to_string());
+        loop {
+          let (part, end_tag) = self.parse_nodes()?;
+          default_slot.extend(part);
+          match end_tag {
+            Some(end_tag) if end_tag.name == "endcomponent" => break,
+            Some(end_tag) if end_tag.name == "slot" => {
+              let slot_name: String = match end_tag.args.as_slice() {
+                [slot_name] => slot_name.clone(),
+                _ => return Err(ErrorKind::BadArgument("`slot:` statement missing slot name (second arg), or more than two args".to_string()).at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index))),
+              };
+              Renderer::check_var_name_legality(&slot_name, false).map_err(
+                |error| error.at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index))
+              )?;
+              if slots.iter().any(|(existing_name, _)| *existing_name == slot_name) {
+                return Err(ErrorKind::BadArgument(format!("`component:` statement has slot `{}` more than once", slot_name)).at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index)));
+              }
+              self.open_statements.push("slot".to_string());
+              let (slot_body, slot_end_tag) = self.parse_nodes()?;
+              match slot_end_tag {
+                Some(slot_end_tag) if slot_end_tag.name == "endslot" => {},
+                _ => return Err(ErrorKind::MissingEndSlot.at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index))),
+              }
+              self.open_statements.pop();
+              slots.push((slot_name, slot_body));
+            },
+            _ => return Err(ErrorKind::MissingEndComponent),
+          }
+        }
+        self.open_statements.pop();
+        body = Some(default_slot);
+      }
+      nodes.push(Node::Component {
+        index,
+        file_name: exp_parts[1].to_string(),
+        props,
+        only,
+        body,
+        slots,
+      });
+    } else if exp_parts[0] == "extends" {
+      if exp_parts.len() != 2 {
+        return Err(ErrorKind::BadArgument("`extends:` statement missing template name (second arg), or more than two args".to_string()));
+      }
+      //only blocks are used from a template that extends another, so it has to be clear from the start
+      if self.index != 1 {
+        return Err(ErrorKind::BadArgument("`extends:` statement must be the first statement in the template".to_string()));
+      }
+      self.extends = Some((exp_parts[1].to_string(), index));
+    } else if exp_parts[0] == "block" {
+      if exp_parts.len() != 2 {
+        return Err(ErrorKind::BadArgument("`block:` statement missing block name (second arg), or more than two args".to_string()));
+      }
+      Renderer::check_var_name_legality(exp_parts[1], false)?;
+      if self.open_statements.iter().any(|statement| statement == "macro") {
+        return Err(ErrorKind::BadArgument("`block:` statement cannot be inside of a macro".to_string()));
+      }
+      if !self.block_names.insert(exp_parts[1].to_string()) {
+        return Err(ErrorKind::BadArgument(format!("Template has more than one `block:` named `{}`", exp_parts[1])));
+      }
+      self.open_statements.push("block".to_string());
+      let (body, end_tag) = self.parse_nodes()?;
+      match end_tag {
+        Some(end_tag) if end_tag.name == "endblock" => {},
+        _ => return Err(ErrorKind::MissingEndBlock),
+      }
+      self.open_statements.pop();
+      nodes.push(Node::Block {
+        index,
+        name: exp_parts[1].to_string(),
+        body,
+      });
+    } else if exp_parts[0] == "macro" {
+      if exp_parts.len() < 2 {
+        return Err(ErrorKind::BadArgument("`macro:` statement missing macro name (second arg)".to_string()));
+      }
+      //macros can be called from anywhere in the template, so they cannot depend on being inside of something
+      if !self.open_statements.is_empty() {
+        return Err(ErrorKind::BadArgument("`macro:` statement cannot be inside of other statements".to_string()));
+      }
+      let name: String = exp_parts[1].to_string();
+      Renderer::check_var_name_legality(&name, false)?;
+      if self.macros.contains_key(&name) {
+        return Err(ErrorKind::BadArgument(format!("Template has more than one `macro:` named `{}`", name)));
+      }
+      let mut arg_names: Vec<String> = Vec::new();
+      for arg_name in &exp_parts[2..] {
+        Renderer::check_var_name_legality(arg_name, false)?;
+        if arg_names.iter().any(|existing_name| existing_name == arg_name) {
+          return Err(ErrorKind::BadArgument(format!("`macro:` statement has arg `{}` more than once", arg_name)));
+        }
+        arg_names.push(arg_name.to_string());
+      }
+      self.open_statements.push("macro".to_string());
+      let (body, end_tag) = self.parse_nodes()?;
+      match end_tag {
+        Some(end_tag) if end_tag.name == "endmacro" => {},
+        _ => return Err(ErrorKind::MissingEndMacro),
+      }
+      self.open_statements.pop();
+      self.macros.insert(name.clone(), Macro {
+        index,
+        name,
+        arg_names,
+        body,
+      });
+    } else if exp_parts[0] == "call" {
+      if exp_parts.len() < 2 {
+        return Err(ErrorKind::BadArgument("`call:` statement missing macro name (second arg)".to_string()));
+      }
+      Renderer::check_var_name_legality(exp_parts[1], false)?;
+      nodes.push(Node::Call {
+        index,
+        name: exp_parts[1].to_string(),
+        args: exp_parts[2..].iter().map(|arg| Expr::parse(arg)).collect::<Result<Vec<Expr>, ErrorKind>>()?,
+      });
+    } else if exp_parts[0] == "import" {
+      if exp_parts.len() != 2 {
+        return Err(ErrorKind::BadArgument("`import:` statement missing template name (second arg), or more than two args".to_string()));
+      }
+      if !self.open_statements.is_empty() {
+        return Err(ErrorKind::BadArgument("`import:` statement cannot be inside of other statements".to_string()));
+      }
+      self.imports.push((exp_parts[1].to_string(), index));
+    } else if exp_parts == ["parent"] && self.open_statements.iter().any(|statement| statement == "block") {
+      //`[[ parent ]]` only means something inside a block, so variables named "parent" can still be used elsewhere
+      nodes.push(Node::Parent {
+        index,
+      });
+    } else if exp_parts[0] == "for" {
+      if exp_parts.len() < 2 {
+        return Err(ErrorKind::BadArgument("`for:` statement missing variable name to loop over (second arg)".to_string()));
+      }
+      //can have filters, but filter args need to be in parentheses, since the loop variable names come after another ":" (eg, `for:(posts|slice:0:10):post`)
+      let source: Expr = Expr::parse(exp_parts[1])?;
+      //iter variable (you know, the "post" in "for post in posts"), index count and max count are all optional
+      //(for hashmaps, it is the key and value variables instead of the iter variable)
+      //whether it is a vector or hashmap isn't known until rendering, so only the max amount of args for hashmaps is checked here
+      if exp_parts.len() > 6 {
+        return Err(ErrorKind::BadArgument("`for:` statement cannot have more than 6 args".to_string()));
+      }
+      let loop_var_names: Vec<String> = exp_parts[2..].iter().map(|loop_var_name| loop_var_name.to_string()).collect();
+      for loop_var_name in &loop_var_names {
+        Renderer::check_var_name_legality(loop_var_name, false)?;
+      }
+      self.open_statements.push("for".to_string());
+      let (body, end_tag) = self.parse_nodes()?;
+      let mut empty_body: Option<Vec<Node>> = None;
+      match end_tag {
+        Some(end_tag) if end_tag.name == "endfor" => {},
+        Some(end_tag) if end_tag.name == "forelse" => {
+          let (body, end_tag) = self.parse_nodes()?;
+          match end_tag {
+            Some(end_tag) if end_tag.name == "endfor" => {},
+            Some(end_tag) if end_tag.name == "forelse" => {
+              return Err(ErrorKind::BadArgument("`for:` statement can only have one `[[ forelse ]]` (or `[[ empty ]]`)".to_string()).at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index)));
+            },
+            //`for:` statement missing `[[ endfor ]]`
+            _ => return Err(ErrorKind::MissingEndFor),
+          }
+          empty_body = Some(body);
+        },
+        //`for:` statement missing `[[ endfor ]]`
+        _ => return Err(ErrorKind::MissingEndFor),
+      }
+      self.open_statements.pop();
+      nodes.push(Node::For {
+        index,
+        source,
+        loop_var_names,
+        body,
+        empty_body,
+      });
+    } else if exp_parts[0] == "if" {
+      let mut branches: Vec<IfBranch> = Vec::new();
+      let mut else_body: Option<Vec<Node>> = None;
+      let mut branch_index: usize = index;
+      let mut condition: Expr = Self::parse_condition("if", &exp_parts[1..])?;
+      self.open_statements.push("if".to_string());
+      loop {
+        let (body, end_tag) = self.parse_nodes()?;
+        //`if:` statement missing `[[ endif ]]`
+        let end_tag: EndTag = end_tag.ok_or(ErrorKind::MissingEndIf)?;
+        branches.push(IfBranch {
+          index: branch_index,
+          condition,
+          body,
+        });
+        branch_index = end_tag.index;
+        if end_tag.name == "elif" {
+          let args: Vec<&str> = end_tag.args.iter().map(|arg| arg.as_str()).collect();
+          condition = Self::parse_condition("elif", &args).map_err(
+            |error| error.at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index))
+          )?;
+        } else if end_tag.name == "else" {
+          let (body, end_tag) = self.parse_nodes()?;
+          match end_tag {
+            Some(end_tag) if end_tag.name == "endif" => {},
+            Some(end_tag) if end_tag.name == "elif" || end_tag.name == "else" => {
+              return Err(ErrorKind::BadArgument(format!("`[[ {} ]]` cannot come after `[[ else ]]`", end_tag.name)).at(SourceLocation::new(self.template_name, self.template_contents, end_tag.index)));
+            },
+            _ => return Err(ErrorKind::MissingEndIf),
+          }
+          else_body = Some(body);
+          break;
+        } else if end_tag.name == "endif" {
+          break;
+        } else {
+          return Err(ErrorKind::MissingEndIf);
+        }
+      }
+      self.open_statements.pop();
+      nodes.push(Node::If {
+        index,
+        branches,
+        else_body,
+      });
+    } else if exp_parts[0] == "endif" || exp_parts[0] == "endfor" || exp_parts[0] == "else" || exp_parts[0] == "elif" || exp_parts[0] == "forelse" || exp_parts[0] == "endcomponent" || exp_parts[0] == "endslot" || exp_parts[0] == "endblock" || exp_parts[0] == "endmacro" || (exp_parts == ["empty"] && self.open_statements.last().map(|statement| statement.as_str()) == Some("for")) {
+      //`[[ empty ]]` is the same as `[[ forelse ]]`, but only directly inside a for loop, so variables named "empty" can still be used elsewhere
+      return Ok(Some(EndTag {
+        name: if exp_parts[0] == "empty" { "forelse".to_string() } else { exp_parts[0].to_string() },
+        args: exp_parts[1..].iter().map(|arg| arg.to_string()).collect(),
+        index,
+      }));
+    } else if exp_parts[0] == "slot" && exp_parts.len() > 1 {
+      //named slots are handled by the `component:` block they are in. `[[ slot ]]` without a name is the variable
+      if self.open_statements.last().map(|statement| statement.as_str()) != Some("component") {
+        return Err(ErrorKind::BadArgument("`slot:` statement can only be directly inside of a `component:` block".to_string()));
+      }
+      return Ok(Some(EndTag {
+        name: "slot".to_string(),
+        args: exp_parts[1..].iter().map(|arg| arg.to_string()).collect(),
+        index,
+      }));
+    } else { //html:<variable name> or <variable name>, with optional filters (eg, `post.title|upper|truncate:60`)
+      //variable
+      let content: &str = &syntax_match.content[3..syntax_match.content.len()-3];
+      let (expr, html): (&str, bool) = match content.strip_prefix("html:") {
+        Some(expr) => (expr, true),
+        None => (content, false),
+      };
+      if html && expr.is_empty() {
+        return Err(ErrorKind::BadArgument("`html:` statement missing variable name (second arg)".to_string()));
+      }
+      nodes.push(Node::Var {
+        index,
+        expr: Expr::parse(expr)?,
+        html,
+      });
+    }
+    Ok(None)
+  }
+}
+
+struct RenderState<'a> {
+  scope: Scope<'a>,
+  for_loops: Vec<ForLoopInfo>,
+  recursion_layer: usize,
+  inheritance: Vec<&'a Template>, //the template being rendered, then the template it extends, and so on, so the base template is last
+  blocks: Vec<(String, usize)>, //`block:`s being rendered, and the index in `inheritance` of the template their content is from, innermost last
+  imports: Vec<Arc<Template>>, //templates imported (`[[ import:name ]]`) by the templates in `inheritance`
+  //the template, then the components and macros being rendered, innermost last. with the variables they see (except the original `Vars`, and for loop metadata),
+  //and the index of the include the original `Vars` are from: the template, or the innermost `only` component (which are `None`, since they see nothing else)
+  includes: Vec<(String, Vars, Option<usize>)>,
+}
+
+/// Whether the `Renderer` keeps parsed templates (and components) around, instead of loading and parsing them every time they are used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheMode {
+  Disabled,
+  Forever, //for production, templates are never reloaded (unless `Renderer::clear_cache` is called)
+  CheckModified, //for development, templates are reloaded if the loader says they were modified since being cached (or cannot say when they were modified)
+}
+
 pub struct Renderer {
   pub templates_dir: String,
   pub components_dir: String,
   pub file_extension: FileExtension,
+  pub max_recursion_depth: usize, //how deep components and macros can be nested inside each other, 5 by default
+  pub loader: Option<Box<dyn TemplateLoader>>, //where templates are loaded from, if `None` they are read from files in `templates_dir`
+  pub cache_mode: CacheMode, //disabled by default
+  cache: Mutex<HashMap<String, CachedTemplate>>, //keyed by resolved path
+  filters: HashMap<String, Filter>, //registered filters, used instead of built in filters with the same name
+  functions: HashMap<String, Function>,
+}
+
+struct CachedTemplate {
+  template: Arc<Template>,
+  modified: Option<SystemTime>,
 }
 
 impl Renderer {
@@ -148,26 +1451,65 @@ impl Renderer {
       templates_dir,
       components_dir,
       file_extension,
+      max_recursion_depth: 5,
+      loader: None,
+      cache_mode: CacheMode::Disabled,
+      cache: Mutex::new(HashMap::new()),
+      filters: HashMap::new(),
+      functions: HashMap::new(),
     }
   }
 
-  pub fn concat_path(path1: &String, path2: &String) -> String {
-    if path1.ends_with("/") && path2.starts_with("/") {
-      let mut path1: String = path1.clone();
-      path1.truncate(path1.len()-1);
-      format!("{}{}", path1, path2)
-    } else if !path1.ends_with("/") && !path2.starts_with("/") {
+  /// Load templates with `loader` instead of from files in a directory. Components are loaded from it too, with names starting with `components_dir`.
+  pub fn with_loader<L: TemplateLoader + 'static>(loader: L, components_dir: String, file_extension: FileExtension) -> Self {
+    Self {
+      templates_dir: String::new(),
+      components_dir,
+      file_extension,
+      max_recursion_depth: 5,
+      loader: Some(Box::new(loader)),
+      cache_mode: CacheMode::Disabled,
+      cache: Mutex::new(HashMap::new()),
+      filters: HashMap::new(),
+      functions: HashMap::new(),
+    }
+  }
+
+  /// Add a filter that can be used in templates, eg `[[ product.cents|price:"USD" ]]`. It gets the value being filtered and the args.
+  /// Replaces the built in (or previously registered) filter with the same name.
+  pub fn register_filter<F>(&mut self, name: &str, filter: F) -> Result<(), ErrorKind> where F: Fn(&VarValue, &[VarValue]) -> Result<VarValue, ErrorKind> + Send + Sync + 'static {
+    Self::check_var_name_legality(name, false)?;
+    self.filters.insert(name.to_string(), Box::new(filter));
+    Ok(())
+  }
+
+  /// Add a function that can be called in templates, eg `[[ route("post", post.slug) ]]`. It gets the args.
+  pub fn register_function<F>(&mut self, name: &str, function: F) -> Result<(), ErrorKind> where F: Fn(&[VarValue]) -> Result<VarValue, ErrorKind> + Send + Sync + 'static {
+    Self::check_var_name_legality(name, false)?;
+    self.functions.insert(name.to_string(), Box::new(function));
+    Ok(())
+  }
+
+  /// Forget all cached templates, so they are loaded again next time they are used.
+  pub fn clear_cache(&self) {
+    self.cache.lock().unwrap().clear();
+  }
+
+  pub fn concat_path(path1: &str, path2: &str) -> String {
+    if path1.ends_with('/') && path2.starts_with('/') {
+      format!("{}{}", &path1[..path1.len()-1], path2)
+    } else if !path1.ends_with('/') && !path2.starts_with('/') {
       format!("{}/{}", path1, path2)
     } else {
       format!("{}{}", path1, path2)
     }
   }
 
-  pub fn sanitize(text: &String) -> String {
-    text.replace("<", "&lt;").replace(">", "&gt;")
+  pub fn sanitize(text: &str) -> String {
+    text.replace('<', "&lt;").replace('>', "&gt;")
   }
 
-  pub fn check_var_name_legality(var_name: &String, dot_allowed: bool) -> Result<(), ErrorKind> {
+  pub fn check_var_name_legality(var_name: &str, dot_allowed: bool) -> Result<(), ErrorKind> {
     let mut legal_chars: Vec<char> = vec!['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '_', '/', '.'];
     if !dot_allowed {
       legal_chars.pop();
@@ -175,334 +1517,569 @@ impl Renderer {
     //if any of them are not in the legal chars
     let fail: bool = var_name.chars().any(|c| !legal_chars.contains(&c.to_ascii_lowercase()));
     if fail {
-      Err(ErrorKind::IllegalVarName(var_name.clone()))
+      Err(ErrorKind::IllegalVarName(var_name.to_string()))
     } else {
       Ok(())
     }
   }
 
-  pub fn find_syntax_matches(template_content: &String) -> Vec<SyntaxMatch> {
-    let legal_chars: Vec<char> = vec!['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '_', '.', ':', '-', '!'];
+  pub fn find_syntax_matches(template_content: &str) -> Vec<SyntaxMatch> {
+    let legal_chars: Vec<u8> = b"abcdefghijklmnopqrstuvwxyz0123456789_.:-! <>=()|,?".to_vec();
     let mut matches: Vec<SyntaxMatch> = Vec::new();
     //"[[  ]]"
-    let chars: Vec<char> = template_content.chars().collect();
+    //the syntax is all ascii, so we can go byte by byte. bytes of non-ascii chars are never ascii, so they can never be mistaken for syntax,
+    //and the indexes are byte indexes that can be used to slice the template
+    let bytes: &[u8] = template_content.as_bytes();
     let mut in_match: bool = false;
+    let mut in_quote: Option<u8> = None; //strings can have any characters (besides newlines) inside of them
     let mut match_index: usize = 0; //start index of match
-    for index in 0..chars.len() {
-      let current_char: char = chars[index];
-      if index > 1 && index < chars.len()-2 {
-        if current_char == ' ' && chars[index-1] == '[' && chars[index-2] == '[' {
-          in_match = true;
-          match_index = index-2;
-        } else if in_match && chars[index] == ' ' && chars[index+1] == ']' && chars[index+2] == ']' {
+    for index in 2..bytes.len().saturating_sub(2) {
+      let current_byte: u8 = bytes[index];
+      if let Some(quote) = in_quote {
+        if current_byte == quote {
+          in_quote = None;
+        } else if current_byte == b'\n' {
+          in_quote = None;
           in_match = false;
+        }
+      } else if current_byte == b' ' && bytes[index-1] == b'[' && bytes[index-2] == b'[' {
+        in_match = true;
+        match_index = index-2;
+      } else if in_match && current_byte == b' ' && bytes[index+1] == b']' && bytes[index+2] == b']' {
+        in_match = false;
+        let content: &str = &template_content[match_index..index+3];
+        if Self::is_syntax(content) {
           matches.push(SyntaxMatch {
             index: match_index,
-            content: chars[match_index..index+3].iter().collect(),
+            content: content.to_string(),
           });
-        } else if in_match && !legal_chars.contains(&current_char.to_ascii_lowercase()) {
-          in_match = false;
         }
+      } else if in_match && (current_byte == b'"' || current_byte == b'\'') {
+        in_quote = Some(current_byte);
+      } else if in_match && !legal_chars.contains(&current_byte.to_ascii_lowercase()) {
+        in_match = false;
       }
     }
     matches
   }
 
+  //`[[ ... ]]` with only letters, numbers and `_.:-!` is always syntax. if it has anything else (spaces, operators, strings, etc), it is only syntax if it is a statement or a valid expression,
+  //so text that happens to be in double brackets, like `[[ see also ]]` or `const m = [[ 1, 2 ]];`, is still left alone
+  fn is_syntax(content: &str) -> bool {
+    let inner: &str = &content[3..content.len()-3];
+    if inner.bytes().all(|byte| b"abcdefghijklmnopqrstuvwxyz0123456789_.:-!".contains(&byte.to_ascii_lowercase())) {
+      return true;
+    }
+    let statements: [&str; 11] = ["if", "elif", "for", "component", "slot", "block", "macro", "call", "extends", "import", "html"];
+    statements.contains(&Parser::split_args(inner)[0]) || Expr::parse(inner).is_ok()
+  }
+
   pub fn get_var(var_name: String, vars: &Vars) -> Result<&VarValue, ErrorKind> {
-    Self::check_var_name_legality(&var_name, true)?;
-    let mut parts: VecDeque<&str> = var_name.split(".").into_iter().collect();
+    Self::get_var_with(var_name, |part_uno| vars.get(part_uno))
+  }
+
+  //`get_first` finds the value of the first part of the variable name (before the first "."), the rest of the parts are looked up in that value
+  //`a?.b` is an optional lookup: if `a` has no `b` (or is not a hashmap), the value is `VarValue::Undefined` instead of an error
+  fn get_var_with<'a>(var_name: String, get_first: impl Fn(&str) -> Option<&'a VarValue>) -> Result<&'a VarValue, ErrorKind> {
+    Self::check_var_name_legality(&var_name.replace("?.", "."), true)?;
+    let mut parts: VecDeque<&str> = var_name.split('.').collect();
     let part_uno: &str = parts.pop_front().unwrap();
-    let var_value_unwrapped: &Option<&VarValue> = &vars.get(part_uno);
+    let mut optional: bool = part_uno.ends_with('?');
+    let var_value_unwrapped: Option<&VarValue> = get_first(part_uno.trim_end_matches('?'));
     if var_value_unwrapped.is_none() {
+      //`missing?.a` is undefined too
+      if optional {
+        return Ok(&UNDEFINED);
+      }
       //bad
       return Err(ErrorKind::VarNotFound(var_name));
     }
     let mut var_value = var_value_unwrapped.unwrap();
     for part in parts {
-      if let VarValue::HashMap(var_value_hashmap) = &var_value {
-        let var_value_hashmap_unwrapped: Option<&VarValue> = var_value_hashmap.get(part);
-        if var_value_hashmap_unwrapped.is_none() {
-          //bad
-          return Err(ErrorKind::VarNotFound(var_name));
-        }
-        var_value = var_value_hashmap_unwrapped.unwrap();
-      } else {
+      let var_value_hashmap_unwrapped: Option<&VarValue> = match var_value {
+        VarValue::HashMap(var_value_hashmap) => var_value_hashmap.get(part.trim_end_matches('?')),
+        _ => None,
+      };
+      match var_value_hashmap_unwrapped {
+        Some(part_value) => var_value = part_value,
+        //the rest of the parts are skipped
+        None if optional => return Ok(&UNDEFINED),
         //bad
-        return Err(ErrorKind::VarNotFound(var_name));
+        None => return Err(ErrorKind::VarNotFound(var_name)),
       }
+      optional = part.ends_with('?');
     }
     Ok(var_value)
   }
 
-  pub fn render(&self, template_contents: String, vars: &mut Vars, recursion_layer: Option<usize>) -> Result<String, ErrorKind> {
-    let recursion_layer: usize = recursion_layer.unwrap_or(0);
-    let syntax_matches: Vec<SyntaxMatch> = Self::find_syntax_matches(&template_contents);
-    if syntax_matches.len() == 0 {
-      return Ok(template_contents);
+  pub fn evaluate<'a>(&self, expr: &'a Expr, scope: &'a Scope) -> Result<Cow<'a, VarValue>, ErrorKind> {
+    match expr {
+      Expr::Var(var_name) => Ok(Cow::Borrowed(scope.get_var(var_name.clone())?)),
+      Expr::Literal(literal) => Ok(Cow::Borrowed(literal)),
+      Expr::Compare(left, compare_op, right) => {
+        let left: Cow<VarValue> = self.evaluate(left, scope)?;
+        let right: Cow<VarValue> = self.evaluate(right, scope)?;
+        let result: bool = match compare_op {
+          CompareOp::Equal => left.equals(&right),
+          CompareOp::NotEqual => !left.equals(&right),
+          _ => {
+            let ordering: Ordering = left.compare(&right).ok_or_else(
+              || ErrorKind::BadArgument(format!("Cannot compare {} and {} with `{}`", left.type_name(), right.type_name(), compare_op))
+            )?;
+            match compare_op {
+              CompareOp::Less => ordering.is_lt(),
+              CompareOp::LessOrEqual => ordering.is_le(),
+              CompareOp::Greater => ordering.is_gt(),
+              _ => ordering.is_ge(),
+            }
+          },
+        };
+        Ok(Cow::Owned(VarValue::Bool(result)))
+      },
+      //`and` and `or` only evaluate the right side if needed
+      Expr::And(left, right) => Ok(Cow::Owned(VarValue::Bool(self.evaluate(left, scope)?.is_truthy() && self.evaluate(right, scope)?.is_truthy()))),
+      Expr::Or(left, right) => Ok(Cow::Owned(VarValue::Bool(self.evaluate(left, scope)?.is_truthy() || self.evaluate(right, scope)?.is_truthy()))),
+      Expr::Not(expr) => Ok(Cow::Owned(VarValue::Bool(!self.evaluate(expr, scope)?.is_truthy()))),
+      Expr::Filter(expr, filter_name, args) => {
+        let value: Cow<VarValue> = self.evaluate(expr, scope)?;
+        //undefined skips the filters (besides `default`), so it can still get a default with `??` after
+        if *value == VarValue::Undefined && filter_name != "default" {
+          return Ok(value);
+        }
+        let attr_filter: bool = filters::ATTR_FILTERS.contains(&filter_name.as_str()) && !self.filters.contains_key(filter_name);
+        let args: Vec<VarValue> = args.iter().map(|arg| match arg {
+          //the attribute name, not a variable
+          Expr::Var(attr) if attr_filter => Ok(VarValue::String(attr.clone())),
+          _ => self.evaluate(arg, scope).map(Cow::into_owned),
+        }).collect::<Result<_, _>>()?;
+        Ok(Cow::Owned(self.apply_filter(filter_name, &value, &args)?))
+      },
+      Expr::Function(function_name, args) => {
+        let function: &Function = self.functions.get(function_name).ok_or_else(
+          || ErrorKind::BadArgument(format!("Function `{}` does not exist", function_name))
+        )?;
+        let args: Vec<VarValue> = args.iter().map(|arg| self.evaluate(arg, scope).map(Cow::into_owned)).collect::<Result<_, _>>()?;
+        Ok(Cow::Owned(function(&args)?))
+      },
+      //unlike the `default` filter, falsy values (eg, empty strings) are kept
+      Expr::Default(expr, default) => match self.evaluate(expr, scope) {
+        Ok(value) if *value != VarValue::Undefined => Ok(value),
+        Ok(_) | Err(ErrorKind::VarNotFound(_)) => self.evaluate(default, scope),
+        Err(error) => Err(error),
+      },
+      Expr::Defined(expr) => match self.evaluate(expr, scope) {
+        Ok(value) => Ok(Cow::Owned(VarValue::Bool(*value != VarValue::Undefined))),
+        Err(ErrorKind::VarNotFound(_)) => Ok(Cow::Owned(VarValue::Bool(false))),
+        Err(error) => Err(error),
+      },
     }
-    let mut rendered: String = template_contents[0..syntax_matches[0].index].to_string();
-    let mut for_loops: Vec<ForLoopInfo> = vec![];
-    let mut index: usize = 0;
-    let mut iterations_: usize = 0;
-    loop {
-      if index == syntax_matches.len() {
-        break;
+  }
+
+  /// Run the filter with the name on the value. Registered filters are used before built in ones.
+  pub fn apply_filter(&self, filter_name: &str, value: &VarValue, args: &[VarValue]) -> Result<VarValue, ErrorKind> {
+    if let Some(filter) = self.filters.get(filter_name) {
+      return filter(value, args);
+    }
+    let filter: filters::BuiltinFilter = filters::get_builtin(filter_name).ok_or_else(
+      || ErrorKind::BadArgument(format!("Filter `{}` does not exist", filter_name))
+    )?;
+    filter(value, args)
+  }
+
+  fn check_condition(&self, condition: &Expr, scope: &Scope) -> Result<bool, ErrorKind> {
+    //make sure it is truthy
+    Ok(self.evaluate(condition, scope)?.is_truthy())
+  }
+
+  //loads the templates that `template` extends, in order, so the base template is last
+  fn load_parents(&self, template: &Template) -> Result<Vec<Arc<Template>>, ErrorKind> {
+    let mut parents: Vec<Arc<Template>> = Vec::new();
+    let mut names: Vec<String> = template.name.iter().cloned().collect();
+    let mut extends: Option<(String, usize)> = template.extends.clone();
+    while let Some((parent_name, index)) = extends {
+      let child: &Template = parents.last().map(|parent| parent.as_ref()).unwrap_or(template);
+      let parent: Arc<Template> = self.load_template(parent_name).map_err(|error| error.at(child.location(index)))?;
+      let name: String = parent.name.clone().unwrap_or_default();
+      if names.contains(&name) {
+        return Err(ErrorKind::BadArgument(format!("`extends:` statements loop back around: {} -> {}", names.join(" -> "), name)).at(child.location(index)));
       }
-      if iterations_ > 75000 {
-        println!("Passed 75000 iterations while rendering, infinite loop?");
+      names.push(name);
+      extends = parent.extends.clone();
+      parents.push(parent);
+    }
+    Ok(parents)
+  }
+
+  //we do not want get into an infinite recursion loop with components (or macros) referring to each other
+  //if a component is already being rendered with the exact same variables, it will just keep including itself, otherwise (eg, a comment thread component rendering its replies) only stop once it gets too deep
+  fn check_recursion(&self, state: &RenderState, include_name: &str, include_vars: &Vars, include_vars_from: &Option<usize>) -> Result<(), ErrorKind> {
+    let chain = |start: usize| -> Vec<String> {
+      state.includes[start..].iter().map(|(name, _, _)| name.clone()).chain(std::iter::once(include_name.to_string())).collect()
+    };
+    if let Some(start) = state.includes.iter().position(|(name, vars, vars_from)| name == include_name && vars == include_vars && vars_from == include_vars_from) {
+      return Err(ErrorKind::RecursionCycle(chain(start)));
+    }
+    if state.recursion_layer > self.max_recursion_depth {
+      return Err(ErrorKind::RecursionTooDeep(self.max_recursion_depth, chain(0)));
+    }
+    Ok(())
+  }
+
+  //index in `includes` of where the original `Vars` being rendered with are from
+  fn vars_from(state: &RenderState) -> Option<usize> {
+    state.includes.iter().rposition(|(_, _, vars_from)| vars_from.is_none())
+  }
+
+  //loads the templates imported by the templates in the inheritance chain
+  fn load_imports(&self, inheritance: &[&Template]) -> Result<Vec<Arc<Template>>, ErrorKind> {
+    let mut imports: Vec<Arc<Template>> = Vec::new();
+    for template in inheritance {
+      for (import_name, index) in &template.imports {
+        imports.push(self.load_template(import_name.clone()).map_err(|error| error.at(template.location(*index)))?);
       }
-      let syntax_match: &SyntaxMatch = &syntax_matches[index];
-      let exp_parts: Vec<&str> = syntax_match.content[3..syntax_match.content.len()-3].split(":").collect();
-      if exp_parts.len() < 1 {
-        return Err(ErrorKind::BadArgument("An empty '[[ ]]' is not valid".to_string()));
+    }
+    Ok(imports)
+  }
+
+  //renders the base template of the inheritance chain (which is just the template, if it does not extend anything)
+  fn render_inherited(&self, state: &mut RenderState, output: &mut Output) -> Result<(), ErrorKind> {
+    let base: &Template = state.inheritance[state.inheritance.len()-1];
+    self.render_nodes(base, &base.nodes, state, output)
+  }
+
+  //renders the first block with the name, starting from `start` in the inheritance chain
+  fn render_block(&self, name: &str, start: usize, state: &mut RenderState, output: &mut Output) -> Result<(), ErrorKind> {
+    let found: Option<(usize, &Template, &Vec<Node>)> = state.inheritance.iter().enumerate().skip(start).find_map(
+      |(inheritance_index, template)| Template::find_block(&template.nodes, name).map(|body| (inheritance_index, *template, body))
+    );
+    let Some((inheritance_index, template, body)) = found else {
+      if start == 0 {
+        return Err(ErrorKind::BadArgument(format!("Block `{}` not found in the template or any template it extends", name)));
       }
-      if exp_parts[0] == "component" {
-        //we do not want get into an infinite recursion loop with components referring to each other
-        if recursion_layer > 5 {
-          return Err(ErrorKind::RecursionTooDeep);
-        }
-        if exp_parts.len() != 2 {
-          return Err(ErrorKind::BadArgument("`component:` statement missing component name (second arg), or more than two args".to_string()));
+      return Err(ErrorKind::BadArgument(format!("`[[ parent ]]` used in block `{}`, but no template it extends has that block", name)));
+    };
+    state.blocks.push((name.to_string(), inheritance_index));
+    let result: Result<(), ErrorKind> = self.render_nodes(template, body, state, output);
+    state.blocks.pop();
+    result
+  }
+
+  fn render_nodes(&self, template: &Template, nodes: &[Node], state: &mut RenderState, output: &mut Output) -> Result<(), ErrorKind> {
+    for node in nodes {
+      self.render_node(template, node, state, output).map_err(
+        |error| match node.index() {
+          Some(index) => error.at(template.location(index)),
+          None => error,
         }
-        let mut file_name: String = exp_parts[1].to_string();
-        if !file_name.contains(".") {
+      )?;
+    }
+    Ok(())
+  }
+
+  fn render_node(&self, template: &Template, node: &Node, state: &mut RenderState, output: &mut Output) -> Result<(), ErrorKind> {
+    match node {
+      Node::Text(text) => output.write(text)?,
+      Node::Component { file_name, props, only, body, slots, .. } => {
+        Self::check_template_path_legality(file_name)?;
+        let include_name: String = format!("component:{}", file_name);
+        let mut file_name: String = file_name.clone();
+        if !file_name.contains('.') {
           file_name += self.file_extension.get_string_ref();
         }
-        rendered += &self.render_template(Self::concat_path(&self.components_dir, &file_name), vars, Some(recursion_layer+1))?;
-      } else if exp_parts[0] == "for" {
-        let mut already_exists: bool = false;
-        let most_recent: Option<&ForLoopInfo> = for_loops.last();
-        if most_recent.is_some() {
-          if most_recent.unwrap().index == index {
-            //for loop already exists, just continue and do nothing
-            already_exists = true;
+        let mut prop_vars: Vars = HashMap::new();
+        //for the block form, slots are rendered where the component is used, and passed in as html strings: `slot` for the default slot, `slots.<name>` for named ones
+        if let Some(body) = body {
+          let mut slot_rendered: String = String::new();
+          self.render_nodes(template, body, state, &mut Output::new(&mut slot_rendered))?;
+          let mut slot_vars: HashMap<String, VarValue> = HashMap::new();
+          for (slot_name, slot_body) in slots {
+            let mut named_slot_rendered: String = String::new();
+            self.render_nodes(template, slot_body, state, &mut Output::new(&mut named_slot_rendered))?;
+            slot_vars.insert(slot_name.clone(), VarValue::String(named_slot_rendered));
           }
+          prop_vars.insert("slot".to_string(), VarValue::String(slot_rendered));
+          prop_vars.insert("slots".to_string(), VarValue::HashMap(slot_vars));
         }
-        if !already_exists {
-          //variables in for loops are not scoped because that would be too much work
-          if exp_parts.len() < 2 {
-            return Err(ErrorKind::BadArgument("`for:` statement missing variable name to loop over (second arg)".to_string()));
-          }
-          let var_name: String = exp_parts[1].to_string();
-          let var_value: &VarValue = Self::get_var(var_name, &vars)?;
-          if let VarValue::Vec(vec_value) = var_value {
-            let vec_value_cloned = vec_value.clone();
-            let vec_length: usize = vec_value.len().clone();
-            let iter_var_name: Option<String>;
-            if exp_parts.len() >= 3 {
-              //set iter variable (optional) (you know, the "post" in "for post in posts")
-              //(I don't know what the actual name of that thing is)
-              let iter_var_name_: String = exp_parts[2].to_string();
-              Self::check_var_name_legality(&iter_var_name_, false)?;
-              iter_var_name = Some(iter_var_name_.clone());
-              //if vec is empty, that is handled later on
-              if vec_value.len() > 0 {
-                vars.insert(iter_var_name_, vec_value[0].clone());
-              }
-            } else {
-              iter_var_name = None;
-            }
-            let index_var_name: Option<String>;
-            if exp_parts.len() >= 4 {
-              //set index count
-              let index_var_name_: String = exp_parts[3].to_string();
-              Self::check_var_name_legality(&index_var_name_, false)?;
-              index_var_name = Some(index_var_name_.clone());
-              vars.insert(index_var_name_, VarValue::U32(0));
-            } else {
-              index_var_name = None;
-            }
-            if exp_parts.len() >= 5 {
-              //set max count
-              let max_var_name: String = exp_parts[4].to_string();
-              Self::check_var_name_legality(&max_var_name, false)?;
-              vars.insert(max_var_name, VarValue::U32(vec_length as u32-1));
-            }
-            for_loops.push(ForLoopInfo {
-              index,
-              total: vec_length,
-              current: 0,
-              var_value: vec_value_cloned,
-              iter_var_name,
-              index_var_name,
-            });
-            //make sure thing we are iterating over isn't empty
-            if vec_length == 0 {
-              //skip straight to the endfor
-              let sliced: Vec<SyntaxMatch> = syntax_matches[index+1..syntax_matches.len()].to_vec();
-              let mut new_index: Option<usize> = None;
-              let mut extra_fors: usize = 0;
-              for i in 0..sliced.len() {
-                let match_content: &String = &sliced[i].content;
-                if match_content.starts_with("[[ for:") {
-                  extra_fors += 1;
-                } else if match_content == "[[ endfor ]]" {
-                  if extra_fors == 0 {
-                    new_index = Some(i);
-                    break;
-                  }
-                  extra_fors -= 1;
-                }
-              }
-              if new_index.is_none() {
-                //`for:` statement missing `[[ endfor ]]`
-                return Err(ErrorKind::MissingEndFor);
-              }
-              index += new_index.unwrap()+1;
-              continue;
-            }
-          } else {
-            return Err(ErrorKind::BadArgument("variable being looped over in `for:` statement is not a vector".to_string()));
-          }
+        for (prop_name, prop_value) in props {
+          prop_vars.insert(prop_name.clone(), self.evaluate(prop_value, &state.scope)?.into_owned());
         }
-      } else if exp_parts[0] == "endfor" {
-        //check if for loop is over, if not, go back to for
-        let for_loops_len: usize = for_loops.len();
-        let current_loop: &mut ForLoopInfo = &mut for_loops[for_loops_len-1];
-        current_loop.current += 1;
-        if current_loop.current >= current_loop.total {
-          //for loop ended, onwards! oh yeah, also remove the current for loop info
-          for_loops.pop();
+        //cycles are found with everything the component sees, except the `loop` metadata of for loops it is in (`loop.depth` changes every level)
+        let (include_vars, include_vars_from): (Vars, Option<usize>) = if *only {
+          (prop_vars.clone(), None)
         } else {
-          //update iter var
-          if current_loop.iter_var_name.is_some() {
-            vars.insert(current_loop.iter_var_name.clone().unwrap(), current_loop.var_value[current_loop.current].clone());
-          }
-          if current_loop.index_var_name.is_some() {
-            vars.insert(current_loop.index_var_name.clone().unwrap(), VarValue::U32(current_loop.current.clone() as u32));
-          }
-          //go back to start of for loop index
-          index = current_loop.index;
-          continue;
+          let mut include_vars: Vars = state.scope.scopes.iter().flatten().filter(|(var_name, _)| *var_name != "loop").map(|(var_name, var_value)| (var_name.clone(), var_value.clone())).collect();
+          include_vars.extend(prop_vars.clone());
+          (include_vars, Self::vars_from(state))
+        };
+        self.check_recursion(state, &include_name, &include_vars, &include_vars_from)?;
+        let component: Arc<Template> = self.load_template_as(Self::concat_path(&self.components_dir, &file_name), TemplateKind::Component)?;
+        let parents: Vec<Arc<Template>> = self.load_parents(&component)?;
+        let inheritance: Vec<&Template> = std::iter::once(&component).chain(parents.iter()).map(|template| template.as_ref()).collect();
+        let imports: Vec<Arc<Template>> = self.load_imports(&inheritance)?;
+        //an `[[ endcomponent ]]` goes with the closest `component:` before it, so make sure content is not lost by going to a component that does not show it
+        if (body.as_ref().is_some_and(|body| !body.is_empty()) || !slots.is_empty()) && !inheritance.iter().copied().chain(imports.iter().map(|import| import.as_ref())).any(Template::uses_slots) {
+          return Err(ErrorKind::BadArgument(format!("`[[ {} ]]` has content (up to the `[[ endcomponent ]]`), but the component does not use `slot` or `slots`. if it is inside of another component's content, use `[[ {} ]][[ endcomponent ]]`", include_name, include_name)));
         }
-      } else if exp_parts[0] == "if" {
-        if exp_parts.len() < 2 {
-          return Err(ErrorKind::BadArgument("`if:` statement missing variable name (second arg)".to_string()));
-        }
-        let var_name: String = exp_parts[1].to_string();
-        let var_value: &VarValue = Self::get_var(var_name, &vars)?;
-        let condition_pass: bool;
-        if exp_parts.len() == 2 {
-          //make sure var is truthy
-          if var_value.is_truthy() {
-            condition_pass = true;
-          } else {
-            condition_pass = false;
-          }
-        } else if exp_parts.len() == 3 {
-          //compare with second var
-          let mut var_name2: String = exp_parts[2].to_string();
-          let mut if_not: bool = false;
-          if var_name2.starts_with("!") {
-            var_name2 = var_name2[1..var_name2.len()].to_string();
-            if_not = true;
-          }
-          let var_value2: &VarValue = Self::get_var(var_name2, &vars)?;
-          if if_not {
-            //make sure the two compared variables are NOT equal
-            if var_value != var_value2 {
-              condition_pass = true;
-            } else {
-              condition_pass = false;
-            }
+        let mut component_state: RenderState = RenderState {
+          scope: if *only {
+            //component only sees its props
+            Scope::new(&prop_vars)
           } else {
-            //regular comparison statement
-            if var_value == var_value2 {
-              condition_pass = true;
-            } else {
-              condition_pass = false;
+            //components see the same variables (including for loop ones) as where they are used, plus their props, but are rendered separately
+            let mut scopes: Vec<Vars> = std::mem::take(&mut state.scope.scopes);
+            scopes.push(prop_vars);
+            Scope {
+              vars: state.scope.vars,
+              scopes,
             }
-          }
+          },
+          for_loops: if *only { Vec::new() } else { std::mem::take(&mut state.for_loops) },
+          recursion_layer: state.recursion_layer+1,
+          inheritance,
+          blocks: Vec::new(),
+          imports,
+          includes: std::mem::take(&mut state.includes),
+        };
+        component_state.includes.push((include_name, include_vars, include_vars_from));
+        let saved_line: (usize, bool, bool) = output.start_include();
+        let result: Result<(), ErrorKind> = self.render_inherited(&mut component_state, output);
+        output.end_include(saved_line);
+        component_state.includes.pop();
+        state.includes = component_state.includes;
+        if !*only {
+          //give back the scopes and for loops, without the props
+          component_state.scope.pop();
+          state.scope.scopes = component_state.scope.scopes;
+          state.for_loops = component_state.for_loops;
+        }
+        result?;
+      },
+      Node::Block { name, .. } => {
+        //the block from the template furthest down the inheritance chain is the one rendered
+        self.render_block(name, 0, state, output)?;
+      },
+      Node::Parent { .. } => {
+        let (name, inheritance_index): (String, usize) = state.blocks.last().cloned().expect("`[[ parent ]]` can only be parsed inside of a block");
+        self.render_block(&name, inheritance_index+1, state, output)?;
+      },
+      Node::Call { name, args, .. } => {
+        //look in the template the call is in first (for macros, the template the macro is from), then the templates in the inheritance chain, then imported templates
+        let imported: Option<Arc<Template>> = state.imports.iter().find(|imported| imported.macros.contains_key(name)).cloned();
+        let macro_template: &Template = if template.macros.contains_key(name) {
+          template
+        } else if let Some(inherited) = state.inheritance.iter().copied().find(|inherited| inherited.macros.contains_key(name)) {
+          inherited
+        } else if let Some(imported) = &imported {
+          imported
         } else {
-          return Err(ErrorKind::BadArgument("`if:` statement cannot have more than 3 args".to_string()));
+          return Err(ErrorKind::BadArgument(format!("Macro `{}` not found", name)));
+        };
+        let macro_def: &Macro = &macro_template.macros[name];
+        if args.len() != macro_def.arg_names.len() {
+          return Err(ErrorKind::BadArgument(format!("Macro `{}` takes {} args, but was called with {}", name, macro_def.arg_names.len(), args.len())));
         }
-        if !condition_pass { //failed condition
-          //skip to the endif
-          let sliced: Vec<SyntaxMatch> = syntax_matches[index+1..syntax_matches.len()].to_vec();
-          let mut new_index: Option<usize> = None;
-          let mut extra_ifs: usize = 0;
-          for i in 0..sliced.len() {
-            let match_content: &String = &sliced[i].content;
-            if match_content.starts_with("[[ if:") {
-              extra_ifs += 1;
-            } else if match_content == "[[ endif ]]" {
-              if extra_ifs == 0 {
-                new_index = Some(i);
-                break;
-              }
-              extra_ifs -= 1;
+        let mut arg_vars: Vars = HashMap::new();
+        for (arg_name, arg) in macro_def.arg_names.iter().zip(args) {
+          arg_vars.insert(arg_name.clone(), self.evaluate(arg, &state.scope)?.into_owned());
+        }
+        //macros can call themselves, so same as components
+        let include_name: String = format!("macro:{}", name);
+        let include_vars: Vars = arg_vars.clone();
+        let include_vars_from: Option<usize> = Self::vars_from(state);
+        self.check_recursion(state, &include_name, &include_vars, &include_vars_from)?;
+        state.includes.push((include_name, include_vars, include_vars_from));
+        //swap out the scopes and for loops of where the macro is called, so it only sees its args and the original `Vars`
+        let scopes: Vec<Vars> = std::mem::replace(&mut state.scope.scopes, vec![arg_vars]);
+        let for_loops: Vec<ForLoopInfo> = std::mem::take(&mut state.for_loops);
+        state.recursion_layer += 1;
+        let result: Result<(), ErrorKind> = self.render_nodes(macro_template, &macro_def.body, state, output);
+        state.recursion_layer -= 1;
+        state.scope.scopes = scopes;
+        state.for_loops = for_loops;
+        state.includes.pop();
+        result?;
+      },
+      Node::For { source, loop_var_names, body, empty_body, .. } => {
+        let mut loop_var_names: VecDeque<String> = loop_var_names.iter().cloned().collect();
+        let (vec_value, keys, key_var_name): (Vec<VarValue>, Option<Vec<String>>, Option<String>) = match self.evaluate(source, &state.scope)?.into_owned() {
+          VarValue::Vec(vec_value) => {
+            if loop_var_names.len() > 3 {
+              return Err(ErrorKind::BadArgument("`for:` statement looping over a vector cannot have more than 5 args".to_string()));
             }
+            (vec_value, None, None)
+          },
+          VarValue::HashMap(hashmap_value) => {
+            //hashmaps have no order, so loop over the keys in sorted order to always render the same thing
+            let mut keys: Vec<String> = hashmap_value.keys().cloned().collect();
+            keys.sort();
+            let values: Vec<VarValue> = keys.iter().map(|key| hashmap_value[key].clone()).collect();
+            (values, Some(keys), loop_var_names.pop_front())
+          },
+          //nothing to loop over, eg `for:post?.tags:tag` when there are no tags
+          VarValue::Undefined => (Vec::new(), None, None),
+          _ => return Err(ErrorKind::BadArgument("variable being looped over in `for:` statement is not a vector or hashmap".to_string())),
+        };
+        let vec_length: usize = vec_value.len();
+        //variables of the for loop are only visible inside of it
+        state.scope.push();
+        if let Some(max_var_name) = loop_var_names.get(2) {
+          state.scope.set(max_var_name.clone(), VarValue::U32(vec_length.saturating_sub(1) as u32));
+        }
+        state.for_loops.push(ForLoopInfo {
+          total: vec_length,
+          current: 0,
+          var_value: vec_value,
+          keys,
+          key_var_name,
+          iter_var_name: loop_var_names.pop_front(),
+          index_var_name: loop_var_names.pop_front(),
+        });
+        //if vec is empty, the body is never rendered (but the `[[ forelse ]]` part is, after)
+        loop {
+          let current_loop: &ForLoopInfo = state.for_loops.last().unwrap();
+          if current_loop.current >= current_loop.total {
+            break;
           }
-          if new_index.is_none() {
-            //`if:` statement missing `[[ endif ]]`
-            return Err(ErrorKind::MissingEndIf);
+          //update iter var (and key var)
+          if let (Some(key_var_name), Some(keys)) = (&current_loop.key_var_name, &current_loop.keys) {
+            state.scope.set(key_var_name.clone(), VarValue::String(keys[current_loop.current].clone()));
           }
-          index += new_index.unwrap()+1;
-          continue;
+          if let Some(iter_var_name) = &current_loop.iter_var_name {
+            state.scope.set(iter_var_name.clone(), current_loop.var_value[current_loop.current].clone());
+          }
+          if let Some(index_var_name) = &current_loop.index_var_name {
+            state.scope.set(index_var_name.clone(), VarValue::U32(current_loop.current as u32));
+          }
+          state.scope.set("loop".to_string(), ForLoopInfo::loop_var(&state.for_loops));
+          self.render_nodes(template, body, state, output)?;
+          state.for_loops.last_mut().unwrap().current += 1;
         }
-      } else if exp_parts[0] == "endif" {
-        //yup, nothing here
-      } else { //html:<variable name> or <variable name>
-        //variable
-        let var_name: String;
-        if exp_parts[0] == "html" {
-          if exp_parts.len() != 2 {
-            return Err(ErrorKind::BadArgument("`html:` statement missing variable name, the second arg, or has more than two args".to_string()));
+        //for loop ended, onwards! oh yeah, also remove the current for loop info and variables
+        state.for_loops.pop();
+        state.scope.pop();
+        if vec_length == 0 {
+          if let Some(empty_body) = empty_body {
+            self.render_nodes(template, empty_body, state, output)?;
           }
-          var_name = exp_parts[1].to_string();
-        } else {
-          var_name = exp_parts[0].to_string();
         }
-        //convert to string
-        let var_value_string: String = (Self::get_var(var_name, &vars)?).to_string();
-        //add indentation
-        let current_last = rendered.split("\n").last().unwrap();
-        let mut indentation: usize = 0;
-        for i in 0..current_last.len() {
-          if current_last.chars().nth(i).unwrap() != ' ' {
-            break;
+      },
+      Node::If { branches, else_body, .. } => {
+        for branch in branches {
+          let condition_pass: bool = self.check_condition(&branch.condition, &state.scope).map_err(
+            |error| error.at(template.location(branch.index))
+          )?;
+          if condition_pass {
+            return self.render_nodes(template, &branch.body, state, output);
           }
-          indentation += 1;
         }
-        let mut var_lines: VecDeque<&str> = var_value_string.split("\n").into_iter().collect();
+        if let Some(else_body) = else_body {
+          self.render_nodes(template, else_body, state, output)?;
+        }
+      },
+      Node::Var { expr, html, .. } => {
+        //convert to string (after any filters)
+        let var_value_string: String = self.evaluate(expr, &state.scope)?.to_string();
+        //add indentation
+        let indentation: usize = output.indentation;
+        let mut var_lines: VecDeque<&str> = var_value_string.split('\n').collect();
         let var_first: &str = var_lines.pop_front().unwrap();
         //append spaces
-        let var_value: String;
-        if var_lines.len() == 0 {
-          var_value = var_first.to_string();
+        let var_value: String = if var_lines.is_empty() {
+          var_first.to_string()
         } else {
-          var_value = format!("{}\n{}", var_first, var_lines.into_iter().map(
+          format!("{}\n{}", var_first, var_lines.into_iter().map(
             |var_line| {
               " ".repeat(indentation)+var_line
             }
-          ).collect::<Vec<String>>().join("\n"));
-        }
-        if exp_parts[0] == "html" {
+          ).collect::<Vec<String>>().join("\n"))
+        };
+        if *html {
           //variable but not sanitized
-          rendered += &var_value;
+          output.write(&var_value)?;
         } else {
-          rendered += &Self::sanitize(&var_value);
+          output.write(&Self::sanitize(&var_value))?;
         }
-      }
-      if index != syntax_matches.len()-1 {
-        //add the html that comes after this, up until the next template syntax match thing
-        rendered += &template_contents[syntax_match.index+syntax_match.content.len()..syntax_matches[index+1].index];
-      } else {
-        //last index, add all the way till end of template
-        rendered += &template_contents[syntax_match.index+syntax_match.content.len()..template_contents.len()];
-      }
-      index += 1;
-      iterations_ += 1;
+      },
     }
-    Ok(rendered)
+    Ok(())
+  }
+
+  pub fn render(&self, template_contents: String, vars: &Vars, recursion_layer: Option<usize>) -> Result<String, ErrorKind> {
+    Template::new(template_contents, None)?.render(self, vars, recursion_layer)
   }
 
-  pub fn render_template(&self, template_name: String, vars: &mut Vars, recursion_layer: Option<usize>) -> Result<String, ErrorKind> {
+  /// Read and parse a template with the loader (or from `templates_dir`, if there is no loader). The file extension is added if `template_name` does not have one.
+  /// If caching is enabled, the cached template is returned if there is one (and it is not stale).
+  pub fn load_template(&self, template_name: String) -> Result<Arc<Template>, ErrorKind> {
+    Self::check_template_path_legality(&template_name)?;
+    self.load_template_as(template_name, TemplateKind::Page)
+  }
+
+  /// Template (and component) names are paths inside of the templates (or components) directory, so they cannot be absolute or go up a directory with `..`.
+  pub fn check_template_path_legality(template_name: &str) -> Result<(), ErrorKind> {
+    let mut parts = template_name.split(['/', '\\']);
+    //absolute paths start with a slash (so the first part is empty), or a windows drive like "C:"
+    let first_part: &str = parts.next().unwrap_or_default();
+    if first_part.is_empty() || first_part.contains(':') || first_part == ".." || parts.any(|part| part == "..") {
+      return Err(ErrorKind::IllegalTemplatePath(template_name.to_string()));
+    }
+    Ok(())
+  }
+
+  fn load_template_as(&self, template_name: String, template_kind: TemplateKind) -> Result<Arc<Template>, ErrorKind> {
     let mut template_file_name = template_name;
-    if !template_file_name.contains(".") {
+    if !template_file_name.contains('.') {
       template_file_name += self.file_extension.get_string_ref();
     }
-    let content: String = fs::read_to_string(Self::concat_path(&self.templates_dir, &template_file_name)).unwrap();
-    self.render(content, vars, recursion_layer)
+    let default_loader: FileSystemLoader;
+    let loader: &dyn TemplateLoader = match &self.loader {
+      Some(loader) => loader.as_ref(),
+      None => {
+        default_loader = FileSystemLoader::new(self.templates_dir.clone());
+        &default_loader
+      },
+    };
+    let path: String = loader.resolve(&template_file_name);
+    let modified: Option<SystemTime> = if self.cache_mode == CacheMode::CheckModified {
+      loader.modified(&template_file_name)
+    } else {
+      None
+    };
+    if let Some(cached) = self.cache.lock().unwrap().get(&path) {
+      let fresh: bool = match self.cache_mode {
+        CacheMode::Disabled => false,
+        CacheMode::Forever => true,
+        CacheMode::CheckModified => modified.is_some() && modified == cached.modified,
+      };
+      if fresh {
+        return Ok(cached.template.clone());
+      }
+    }
+    let content: String = loader.load(&template_file_name).map_err(|error| match error.kind() {
+      io::ErrorKind::NotFound => ErrorKind::TemplateNotFound(path.clone(), template_kind),
+      _ => ErrorKind::Io(path.clone(), template_kind, error),
+    })?;
+    let template: Arc<Template> = Arc::new(Template::new(content, Some(template_file_name))?);
+    if self.cache_mode != CacheMode::Disabled {
+      self.cache.lock().unwrap().insert(path, CachedTemplate {
+        template: template.clone(),
+        modified,
+      });
+    }
+    Ok(template)
+  }
+
+  pub fn render_template(&self, template_name: String, vars: &Vars, recursion_layer: Option<usize>) -> Result<String, ErrorKind> {
+    self.load_template(template_name)?.render(self, vars, recursion_layer)
+  }
+
+  /// See `Template::render_to`.
+  pub fn render_template_to<W: io::Write>(&self, writer: &mut W, template_name: String, vars: &Vars, recursion_layer: Option<usize>) -> Result<(), ErrorKind> {
+    self.load_template(template_name)?.render_to(writer, self, vars, recursion_layer)
+  }
+
+  /// See `Template::render_to_fmt`.
+  pub fn render_template_to_fmt<W: fmt::Write>(&self, writer: &mut W, template_name: String, vars: &Vars, recursion_layer: Option<usize>) -> Result<(), ErrorKind> {
+    self.load_template(template_name)?.render_to_fmt(writer, self, vars, recursion_layer)
   }
 }